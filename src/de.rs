@@ -0,0 +1,589 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use serde::forward_to_deserialize_any;
+
+use crate::error::{Error, Result};
+use crate::parser::{parse_config_file, parse_config_str, ParseOptions, ParsedConfig};
+use crate::value::{parse_duration, parse_wide_int, ConfigValue};
+
+/// 設定ファイルを読み込み、`T` に変換する。
+///
+/// 解析時にエラーが報告された場合や変換に失敗した場合は、キーパスと位置を含むエラーを返す。
+pub fn from_path<T: DeserializeOwned>(file_path: &Path) -> Result<T> {
+    let parsed = parse_config_file(file_path, &ParseOptions::default())?.into_result()?;
+    from_parsed(&parsed)
+}
+
+/// 文字列から設定を読み込み、`T` に変換する。
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T> {
+    let parsed = parse_config_str(source, Path::new("<入力>"), &ParseOptions::default()).into_result()?;
+    from_parsed(&parsed)
+}
+
+/// 読み込み済みの設定を `T` に変換する。エラーには失敗したキーの定義位置を付ける。
+pub fn from_parsed<'de, T: de::Deserialize<'de>>(parsed: &'de ParsedConfig) -> Result<T> {
+    from_config(&parsed.values).map_err(|e| match e {
+        Error::Deserialize { key, message, .. } => {
+            // キー自体が存在しない場合は、存在する最も近いセクションの位置を示す
+            let span = parsed.nearest_span(&key).map(|span| span.value.unwrap_or(span.key));
+            Error::Deserialize { path: parsed.file_of(span).map(Path::to_path_buf), span, key, message }
+        }
+        e => e,
+    })
+}
+
+/// ネストした設定を `T` に変換する。ドット区切りのセクションはネストした構造体に対応する。
+pub fn from_config<'de, T: de::Deserialize<'de>>(config: &'de HashMap<String, ConfigValue>) -> Result<T> {
+    T::deserialize(ConfigMapDeserializer { map: config })
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Deserialize { path: None, span: None, key: String::new(), message: msg.to_string() }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error::Deserialize {
+            path: None,
+            span: None,
+            key: field.to_string(),
+            message: "必須のキーが存在しません".to_string(),
+        }
+    }
+}
+
+impl Error {
+    // 変換エラーが上位のMapを通過するたびにキーを前に付け足し、ドット区切りのキーパスにする
+    fn prefixed(self, parent: &str) -> Self {
+        match self {
+            Error::Deserialize { path, span, key, message } => {
+                let key = if key.is_empty() { parent.to_string() } else { format!("{}.{}", parent, key) };
+                Error::Deserialize { path, span, key, message }
+            }
+            e => e,
+        }
+    }
+}
+
+/// トップレベルの設定 (`HashMap<String, ConfigValue>`) のデシリアライザ。
+pub struct ConfigMapDeserializer<'de> {
+    map: &'de HashMap<String, ConfigValue>,
+}
+
+impl<'de> ConfigMapDeserializer<'de> {
+    pub fn new(map: &'de HashMap<String, ConfigValue>) -> Self {
+        ConfigMapDeserializer { map }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ConfigMapDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(MapAccess::new(self.map))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for &'de ConfigValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+// 文字列の値は、変換先の型が数値や真偽値を求める場合に限りその型として解釈する。
+// 整数は `--detect-types` やスキーマの `int` と同じく16進数・8進数も受け付ける
+macro_rules! deserialize_integer {
+    ($($method:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            match self {
+                ConfigValue::String(s) => match parse_wide_int(s) {
+                    Some(n) => visit_wide_int(n, visitor),
+                    None => Err(de::Error::invalid_value(Unexpected::Str(s), &visitor)),
+                },
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+// 浮動小数点数は整数と同じ形式の値を整数として解釈し、それ以外の値を `parse` で解釈する
+macro_rules! deserialize_float {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            match self {
+                ConfigValue::String(s) => match (parse_wide_int(s), s.parse()) {
+                    (Some(n), _) => visit_wide_int(n, visitor),
+                    (None, Ok(f)) => visitor.$visit(f),
+                    (None, Err(_)) => Err(de::Error::invalid_value(Unexpected::Str(s), &visitor)),
+                },
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+// `i64` に収まる整数は `visit_i64` で、収まらない整数は `visit_u64` または `visit_i128` で渡す
+fn visit_wide_int<'de, V: Visitor<'de>>(n: i128, visitor: V) -> Result<V::Value> {
+    match (i64::try_from(n), u64::try_from(n)) {
+        (Ok(n), _) => visitor.visit_i64(n),
+        (Err(_), Ok(n)) => visitor.visit_u64(n),
+        _ => visitor.visit_i128(n),
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &'de ConfigValue {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            ConfigValue::String(s) => visitor.visit_borrowed_str(s),
+            ConfigValue::Bool(b) => visitor.visit_bool(*b),
+            ConfigValue::Map(m) => visitor.visit_map(MapAccess::new(m)),
+            ConfigValue::Int(n) => visitor.visit_i64(*n),
+            ConfigValue::Float(f) => visitor.visit_f64(*f),
+            ConfigValue::Duration(d) => visitor.visit_f64(d.as_secs_f64()),
+            ConfigValue::Bytes(b) => visitor.visit_u64(*b),
+            ConfigValue::List(items) => visitor.visit_seq(SeqAccess { iter: items.iter().enumerate() }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            ConfigValue::String(s) => match s.to_ascii_lowercase().as_str() {
+                "1" | "yes" | "on" => visitor.visit_bool(true),
+                "0" | "no" | "off" => visitor.visit_bool(false),
+                _ => Err(de::Error::invalid_value(Unexpected::Str(s), &visitor)),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+    }
+
+    deserialize_float! {
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            ConfigValue::String(s) => match s.parse() {
+                Ok(c) => visitor.visit_char(c),
+                Err(_) => Err(de::Error::invalid_value(Unexpected::Str(s), &visitor)),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            ConfigValue::Bool(b) => visitor.visit_str(if *b { "true" } else { "false" }),
+            ConfigValue::Int(n) => visitor.visit_string(n.to_string()),
+            ConfigValue::Float(f) => visitor.visit_string(f.to_string()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self {
+            ConfigValue::String(s) => visitor.visit_enum(BorrowedStrDeserializer::new(s)),
+            // `mode.tcp.port = 80` のように、キーが1つだけのセクションはそのキーをバリアント名とする
+            ConfigValue::Map(m) if m.len() == 1 => {
+                let (variant, value) = m.iter().next().unwrap();
+                visitor.visit_enum(EnumAccess { variant, value })
+            }
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        // std::time::Duration は `secs` と `nanos` を持つ構造体としてデシリアライズされる
+        let duration = match self {
+            ConfigValue::Duration(d) => Some(*d),
+            ConfigValue::String(s) => parse_duration(s),
+            _ => None,
+        };
+        match duration {
+            Some(d) if name == "Duration" && fields == ["secs", "nanos"] => {
+                let entries = [("secs", d.as_secs()), ("nanos", u64::from(d.subsec_nanos()))];
+                visitor.visit_map(MapDeserializer::new(entries.into_iter()))
+            }
+            _ => self.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            // 連番のキーは配列にまとめられるため、Map を求められた場合は添字をキーとして渡す
+            ConfigValue::List(items) => visitor.visit_map(MapAccess::from_list(items)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf seq tuple tuple_struct identifier
+    }
+}
+
+impl ConfigValue {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            ConfigValue::String(s) => Unexpected::Str(s),
+            ConfigValue::Bool(b) => Unexpected::Bool(*b),
+            ConfigValue::Map(_) => Unexpected::Map,
+            ConfigValue::Int(n) => Unexpected::Signed(*n),
+            ConfigValue::Float(f) => Unexpected::Float(*f),
+            ConfigValue::Duration(_) => Unexpected::Other("duration"),
+            ConfigValue::Bytes(b) => Unexpected::Unsigned(*b),
+            ConfigValue::List(_) => Unexpected::Seq,
+        }
+    }
+}
+
+struct MapAccess<'de> {
+    iter: Box<dyn ExactSizeIterator<Item = (Cow<'de, str>, &'de ConfigValue)> + 'de>,
+    current: Option<(Cow<'de, str>, &'de ConfigValue)>,
+}
+
+impl<'de> MapAccess<'de> {
+    fn new(map: &'de HashMap<String, ConfigValue>) -> Self {
+        MapAccess { iter: Box::new(map.iter().map(|(key, value)| (Cow::Borrowed(key.as_str()), value))), current: None }
+    }
+
+    fn from_list(items: &'de [ConfigValue]) -> Self {
+        MapAccess { iter: Box::new(items.iter().enumerate().map(|(i, value)| (Cow::Owned(i.to_string()), value))), current: None }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.current = Some((key.clone(), value));
+                seed.deserialize(KeyDeserializer { key }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (key, value) = self.current.take().expect("next_key_seed の後に呼び出す");
+        seed.deserialize(value).map_err(|e| e.prefixed(&key))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+// Map のキーのデシリアライザ。`BTreeMap<u32, _>` のように整数のキーも値と同じ形式で解釈する
+struct KeyDeserializer<'de> {
+    key: Cow<'de, str>,
+}
+
+macro_rules! deserialize_key_integer {
+    ($($method:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            match parse_wide_int(&self.key) {
+                Some(n) => visit_wide_int(n, visitor),
+                None => self.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.key {
+            Cow::Borrowed(key) => visitor.visit_borrowed_str(key),
+            Cow::Owned(key) => visitor.visit_string(key),
+        }
+    }
+
+    deserialize_key_integer! {
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.key {
+            Cow::Borrowed(key) => visitor.visit_enum(BorrowedStrDeserializer::new(key)),
+            Cow::Owned(key) => visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(key)),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf option unit unit_struct newtype_struct
+        seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqAccess<'de> {
+    iter: std::iter::Enumerate<std::slice::Iter<'de, ConfigValue>>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some((index, value)) => seed.deserialize(value).map(Some).map_err(|e| e.prefixed(&index.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess<'de> {
+    variant: &'de String,
+    value: &'de ConfigValue,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = Error;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, VariantAccess { variant: self.variant, value: self.value }))
+    }
+}
+
+struct VariantAccess<'de> {
+    variant: &'de String,
+    value: &'de ConfigValue,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(de::Error::invalid_type(self.value.unexpected(), &"unit variant"))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self.value).map_err(|e| e.prefixed(self.variant))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self.value, visitor).map_err(|e| e.prefixed(self.variant))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self.value, visitor).map_err(|e| e.prefixed(self.variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Log {
+        file: String,
+        size: u32,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Settings {
+        log: Log,
+    }
+
+    fn deserialize_error<T: std::fmt::Debug>(result: Result<T>) -> (String, String, Option<usize>) {
+        match result {
+            Err(Error::Deserialize { key, message, span, .. }) => (key, message, span.map(|span| span.line)),
+            other => panic!("Deserialize を期待しましたが {:?} でした", other),
+        }
+    }
+
+    #[test]
+    fn errors_report_the_key_path_and_location() {
+        let (key, message, line) = deserialize_error(from_str::<Settings>("log.file = a.log\nlog.size = big\n"));
+
+        assert_eq!(key, "log.size");
+        assert!(message.contains("big"), "{}", message);
+        assert_eq!(line, Some(2));
+    }
+
+    #[test]
+    fn missing_fields_point_at_the_nearest_section() {
+        let (key, message, line) = deserialize_error(from_str::<Settings>("# ログ\nlog.file = a.log\n"));
+
+        assert_eq!(key, "log.size");
+        assert_eq!(message, "必須のキーが存在しません");
+        assert_eq!(line, Some(2));
+    }
+
+    #[test]
+    fn strings_are_coerced_to_the_requested_type() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Numbers {
+            port: u16,
+            mode: u32,
+            offset: i64,
+            ratio: f64,
+            scale: f32,
+            big: u128,
+        }
+
+        let numbers: Numbers = from_str("port = 0x1F\nmode = 0755\noffset = -0o10\nratio = 0x10\nscale = 1.5\nbig = 18446744073709551616\n").unwrap();
+        assert_eq!(numbers, Numbers { port: 31, mode: 0o755, offset: -8, ratio: 16.0, scale: 1.5, big: 1 << 64 });
+
+        let (key, _, _) = deserialize_error(from_str::<Numbers>("port = 08\nmode = 1\noffset = 1\nratio = 1\nscale = 1\nbig = 1\n"));
+        assert_eq!(key, "port");
+        let (key, _, _) = deserialize_error(from_str::<Numbers>("port = 70000\nmode = 1\noffset = 1\nratio = 1\nscale = 1\nbig = 1\n"));
+        assert_eq!(key, "port");
+    }
+
+    #[test]
+    fn bools_accept_on_off_yes_no_and_digits() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flags {
+            a: bool,
+            b: bool,
+            c: bool,
+            d: bool,
+            e: bool,
+        }
+
+        let flags: Flags = from_str("a = on\nb = OFF\nc = yes\nd = 0\ne = true\n").unwrap();
+        assert_eq!(flags, Flags { a: true, b: false, c: true, d: false, e: true });
+        assert_eq!(deserialize_error(from_str::<Flags>("a = maybe\nb = 1\nc = 1\nd = 1\ne = 1\n")).0, "a");
+    }
+
+    #[test]
+    fn enums_are_read_from_strings_and_single_key_sections() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Level {
+            Debug,
+            Info,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Mode {
+            Tcp(u16),
+            Unix { path: String },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            level: Level,
+            mode: Mode,
+            fallback: Mode,
+        }
+
+        let server: Server = from_str("level = info\nmode.tcp = 80\nfallback.unix.path = /run/app.sock\n").unwrap();
+        assert_eq!(server, Server { level: Level::Info, mode: Mode::Tcp(80), fallback: Mode::Unix { path: "/run/app.sock".to_string() } });
+
+        let (key, _, _) = deserialize_error(from_str::<Server>("level = trace\nmode.tcp = 80\nfallback.tcp = 81\n"));
+        assert_eq!(key, "level");
+        let (key, _, _) = deserialize_error(from_str::<Server>("level = debug\nmode.tcp = http\nfallback.tcp = 81\n"));
+        assert_eq!(key, "mode.tcp");
+    }
+
+    #[test]
+    fn indexed_sections_can_be_read_as_maps() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Maps {
+            by_number: BTreeMap<u32, String>,
+            by_name: HashMap<String, String>,
+            lists: Vec<String>,
+        }
+
+        let maps: Maps = from_str("by_number.0 = a\nby_number.1 = b\nby_name.0 = x\nlists.0 = p\nlists.1 = q\n").unwrap();
+        assert_eq!(maps.by_number, BTreeMap::from([(0, "a".to_string()), (1, "b".to_string())]));
+        assert_eq!(maps.by_name, HashMap::from([("0".to_string(), "x".to_string())]));
+        assert_eq!(maps.lists, ["p", "q"]);
+    }
+}
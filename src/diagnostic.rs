@@ -0,0 +1,155 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::parser::{SourceFile, Span};
+
+/// 診断の重大度。`Warning < Error` の順に並ぶ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// 解析・検証で見つかった問題。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// `type-mismatch` や `duplicate-key` などの識別子
+    pub code: &'static str,
+    /// 対象のキーパス。行単位の構文エラーでは空文字列
+    pub key: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub file: Option<PathBuf>,
+    pub span: Option<Span>,
+    pub message: String,
+    pub hint: Option<String>,
+    /// 先の定義など、あわせて示す位置とその説明
+    pub related: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(severity: Severity, code: &'static str, key: &str, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            key: key.to_string(),
+            expected: None,
+            actual: None,
+            file: None,
+            span: None,
+            message,
+            hint: None,
+            related: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_types(mut self, expected: &str, actual: &str) -> Self {
+        self.expected = Some(expected.to_string());
+        self.actual = Some(actual.to_string());
+        self
+    }
+
+    pub(crate) fn with_file(mut self, file: &Path) -> Self {
+        self.file = Some(file.to_path_buf());
+        self
+    }
+
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub(crate) fn with_hint(mut self, hint: String) -> Self {
+        self.hint = Some(hint);
+        self
+    }
+
+    pub(crate) fn with_related(mut self, span: Span, label: &str) -> Self {
+        self.related.push((span, label.to_string()));
+        self
+    }
+
+    pub(crate) fn with_optional_span(mut self, span: Option<Span>) -> Self {
+        self.span = span.or(self.span);
+        self
+    }
+
+    /// 診断を [`Error`] に変換する。
+    pub fn into_error(self) -> Error {
+        let Diagnostic { code, key, file: path, span, message, .. } = self;
+        match code {
+            "syntax-error" => Error::Syntax { path, span, message },
+            "key-conflict" | "duplicate-key" => Error::Conflict { path, span, key, message },
+            _ => Error::Validation { path, span, key, message },
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.severity {
+            Severity::Warning => "警告",
+            Severity::Error => "エラー",
+        }
+    }
+
+    /// rustcと同様に、該当行を引用して問題の箇所にキャレットを付けた複数行の文字列に整形する。
+    /// `files` には診断を生成した [`ParsedConfig::files`](crate::ParsedConfig::files) を渡す。
+    pub fn render(&self, files: &[SourceFile]) -> String {
+        let mut out = format!("{}[{}]: {}", self.label(), self.code, self.message);
+        let file = match &self.file {
+            Some(file) => file,
+            None => return out,
+        };
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!("\n --> {}", file.display()));
+                return out;
+            }
+        };
+
+        let width = self.related.iter().map(|(related, _)| related.line).chain([span.line]).max().unwrap().to_string().len();
+        let gutter = " ".repeat(width);
+        out.push_str(&format!("\n{}--> {}:{}:{}", gutter, file.display(), span.line, span.start));
+        out.push_str(&format!("\n{} |", gutter));
+        push_snippet(&mut out, &files[span.file].text, span, '^', None, width);
+        for (related, label) in &self.related {
+            if related.file != span.file {
+                let path = &files[related.file].path;
+                out.push_str(&format!("\n{}::: {}:{}:{}", gutter, path.display(), related.line, related.start));
+            }
+            out.push_str(&format!("\n{} |", gutter));
+            push_snippet(&mut out, &files[related.file].text, *related, '-', Some(label), width);
+        }
+        if let Some(hint) = &self.hint {
+            out.push_str(&format!("\n{} = ヒント: {}", gutter, hint));
+        }
+        out
+    }
+}
+
+fn push_snippet(out: &mut String, source: &str, span: Span, marker: char, label: Option<&str>, width: usize) {
+    let text = source.lines().nth(span.line - 1).unwrap_or("");
+    out.push_str(&format!("\n{:>width$} | {}", span.line, text));
+    out.push_str(&format!(
+        "\n{} | {}{}",
+        " ".repeat(width),
+        " ".repeat(span.start - 1),
+        marker.to_string().repeat((span.end - span.start).max(1)),
+    ));
+    if let Some(label) = label {
+        out.push_str(&format!(" {}", label));
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.label(), self.code, self.message)?;
+        match (&self.file, self.span) {
+            (Some(file), Some(span)) => write!(f, " ({}:{}:{})", file.display(), span.line, span.start),
+            (Some(file), None) => write!(f, " ({})", file.display()),
+            _ => Ok(()),
+        }
+    }
+}
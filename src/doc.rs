@@ -0,0 +1,131 @@
+use crate::schema::{raw_text, Presence, Schema};
+
+const HEADERS: [&str; 5] = ["キー", "型", "制約", "既定値", "説明"];
+
+// 表の1行になるキーの情報
+struct Row<'a> {
+    key: &'a str,
+    value_type: &'a str,
+    presence: Option<&'static str>,
+    constraints: Vec<String>,
+    default: Option<String>,
+    description: Option<&'a str>,
+    example: Option<&'a str>,
+}
+
+impl Row<'_> {
+    // 各列の内容を作る。`text` は文章を、`code` は型や値を出力形式に合わせて書式化する
+    fn cells(&self, text: fn(&str) -> String, code: fn(&str) -> String) -> [String; 5] {
+        let constraints: Vec<String> = self.presence.map(text).into_iter().chain(self.constraints.iter().map(|constraint| code(constraint))).collect();
+        let mut description = self.description.map(text).unwrap_or_default();
+        if let Some(example) = self.example {
+            let separator = if description.is_empty() { "" } else { "<br>" };
+            description.push_str(&format!("{}例: {}", separator, code(example)));
+        }
+        [code(self.key), code(self.value_type), constraints.join(" "), self.default.as_deref().map(code).unwrap_or_default(), description]
+    }
+}
+
+fn rows(schema: &Schema) -> Vec<Row<'_>> {
+    let mut rows: Vec<Row> = schema
+        .iter()
+        .map(|(key, field)| Row {
+            key,
+            value_type: &field.value_type,
+            presence: match field.presence {
+                Presence::Required => Some("必須"),
+                Presence::Optional => Some("省略可"),
+                Presence::Expected => None,
+            },
+            constraints: field.constraint_texts(),
+            default: field.default.as_ref().map(raw_text),
+            description: field.description.as_deref(),
+            example: field.example.as_deref(),
+        })
+        .collect();
+    rows.sort_by_key(|row| row.key);
+    rows
+}
+
+/// スキーマをキー・型・制約・既定値・説明の表にした Markdown の文書にする。値の例は説明の列に記載する。
+pub fn format_schema_markdown(schema: &Schema, title: &str) -> String {
+    // 説明は Markdown として書けるようにそのまま出力し、表の区切りと HTML のタグとみなされる文字のみ置き換える
+    fn text(text: &str) -> String {
+        text.replace('|', "\\|").replace('<', "&lt;").replace('\n', " ")
+    }
+    fn code(text: &str) -> String {
+        format!("`{}`", text.replace('|', "\\|").replace('\n', " "))
+    }
+
+    let mut document = format!("# {}\n\n| {} |\n|{}\n", title, HEADERS.join(" | "), " --- |".repeat(HEADERS.len()));
+    for row in rows(schema) {
+        document.push_str(&format!("| {} |\n", row.cells(text, code).join(" | ")));
+    }
+    document
+}
+
+/// スキーマを [`format_schema_markdown`] と同じ表にした HTML の文書にする。
+pub fn format_schema_html(schema: &Schema, title: &str) -> String {
+    fn text(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    }
+    fn code(value: &str) -> String {
+        format!("<code>{}</code>", text(value))
+    }
+
+    let mut document = format!(
+        "<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n\
+         <table>\n<thead>\n<tr><th>{1}</th></tr>\n</thead>\n<tbody>\n",
+        text(title),
+        HEADERS.join("</th><th>"),
+    );
+    for row in rows(schema) {
+        document.push_str(&format!("<tr><td>{}</td></tr>\n", row.cells(text, code).join("</td><td>")));
+    }
+    document.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::parse_schema_str;
+
+    fn schema() -> Schema {
+        parse_schema_str(
+            "## <b>太字</b> & \"引用\" | 区切り\n## example: a|b\nmode -> enum(fast|slow) required\n\
+             log.level -> string = info\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn markdown_escapes_table_separators_and_tags() {
+        let document = format_schema_markdown(&schema(), "設定");
+
+        assert!(document.starts_with("# 設定\n\n| キー | 型 | 制約 | 既定値 | 説明 |\n| --- | --- | --- | --- | --- |\n"), "{}", document);
+        assert!(document.contains("| `log.level` | `string` |  | `info` |  |\n"), "{}", document);
+        assert!(
+            document.contains("| `mode` | `string` | 必須 `enum(fast\\|slow)` |  | &lt;b>太字&lt;/b> & \"引用\" \\| 区切り<br>例: `a\\|b` |\n"),
+            "{}",
+            document
+        );
+    }
+
+    #[test]
+    fn html_escapes_text_and_code() {
+        let document = format_schema_html(&schema(), "<設定>");
+
+        assert!(document.contains("<title>&lt;設定&gt;</title>"), "{}", document);
+        assert!(
+            document.contains(
+                "<tr><td><code>mode</code></td><td><code>string</code></td><td>必須 <code>enum(fast|slow)</code></td><td></td>\
+                 <td>&lt;b&gt;太字&lt;/b&gt; &amp; &quot;引用&quot; | 区切り<br>例: <code>a|b</code></td></tr>"
+            ),
+            "{}",
+            document
+        );
+        // キーの順に並べる
+        assert!(document.find("log.level").unwrap() < document.find("<code>mode</code>").unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::Utf8Error;
+
+use crate::parser::Span;
+
+/// ライブラリの各関数が返すエラー。
+///
+/// 解析・検証で見つかった問題は通常 [`Diagnostic`](crate::Diagnostic) として報告され、
+/// [`Diagnostic::into_error`](crate::Diagnostic::into_error) でこの型に変換できる。
+#[derive(Debug)]
+pub enum Error {
+    /// ファイルの読み込みや探索に失敗した
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// 内容をUTF-8として解釈できない。`span` は最初の不正なバイトの位置
+    Encoding { path: Option<PathBuf>, span: Option<Span>, source: Utf8Error },
+    /// 設定ファイルの行を解析できない
+    Syntax { path: Option<PathBuf>, span: Option<Span>, message: String },
+    /// キーの重複や、スカラー値とセクションの衝突
+    Conflict { path: Option<PathBuf>, span: Option<Span>, key: String, message: String },
+    /// スキーマファイルの行を解析できない
+    SchemaSyntax { path: Option<PathBuf>, span: Option<Span>, message: String },
+    /// 設定がスキーマを満たしていない
+    Validation { path: Option<PathBuf>, span: Option<Span>, key: String, message: String },
+    /// 設定を構造体などに変換できない。`key` は変換に失敗したドット区切りのキーパス
+    Deserialize { path: Option<PathBuf>, span: Option<Span>, key: String, message: String },
+    /// 値を sysctl.conf 形式で出力できない。`key` は出力に失敗したドット区切りのキーパス
+    Serialize { key: String, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn io(path: Option<&Path>, source: io::Error) -> Self {
+        Error::Io { path: path.map(Path::to_path_buf), source }
+    }
+
+    /// エラーの原因となったファイル。
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Serialize { .. } => None,
+            Error::Io { path, .. }
+            | Error::Encoding { path, .. }
+            | Error::Syntax { path, .. }
+            | Error::Conflict { path, .. }
+            | Error::SchemaSyntax { path, .. }
+            | Error::Validation { path, .. }
+            | Error::Deserialize { path, .. } => path.as_deref(),
+        }
+    }
+
+    /// エラーの原因となった位置。
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Io { .. } | Error::Serialize { .. } => None,
+            Error::Encoding { span, .. }
+            | Error::Syntax { span, .. }
+            | Error::Conflict { span, .. }
+            | Error::SchemaSyntax { span, .. }
+            | Error::Validation { span, .. }
+            | Error::Deserialize { span, .. } => *span,
+        }
+    }
+
+    pub(crate) fn with_path(mut self, file: &Path) -> Self {
+        match &mut self {
+            Error::Io { path, .. }
+            | Error::Encoding { path, .. }
+            | Error::Syntax { path, .. }
+            | Error::Conflict { path, .. }
+            | Error::SchemaSyntax { path, .. }
+            | Error::Validation { path, .. }
+            | Error::Deserialize { path, .. } => *path = Some(file.to_path_buf()),
+            Error::Serialize { .. } => (),
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { source, .. } => write!(f, "{}", source)?,
+            Error::Encoding { source, .. } => write!(f, "UTF-8として解釈できません: {}", source)?,
+            Error::Syntax { message, .. }
+            | Error::Conflict { message, .. }
+            | Error::SchemaSyntax { message, .. }
+            | Error::Validation { message, .. } => write!(f, "{}", message)?,
+            Error::Deserialize { key, message, .. } if key.is_empty() => write!(f, "設定を変換できません: {}", message)?,
+            Error::Deserialize { key, message, .. } => write!(f, "キー '{}' を変換できません: {}", key, message)?,
+            Error::Serialize { key, message } if key.is_empty() => write!(f, "設定を出力できません: {}", message)?,
+            Error::Serialize { key, message } => write!(f, "キー '{}' を出力できません: {}", key, message)?,
+        }
+        match (self.path(), self.span()) {
+            (Some(path), Some(span)) => write!(f, " ({}:{}:{})", path.display(), span.line, span.start),
+            (Some(path), None) => write!(f, " ({})", path.display()),
+            (None, Some(span)) => write!(f, " (行 {}:{})", span.line, span.start),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Encoding { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
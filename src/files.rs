@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// ファイルならそのまま、ディレクトリなら直下のファイルをパス順に返す。
+pub fn collect_text_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if path.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| Error::io(Some(path), e))?
+            .filter_map(std::result::Result::ok)
+            .map(|entry| entry.path())
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+        return Ok(files);
+    }
+    Err(Error::io(Some(path), io::Error::new(io::ErrorKind::NotFound, "パスが見つかりません")))
+}
+
+/// 複数のファイル・ディレクトリから [`collect_text_files`] でファイルを集める。
+///
+/// 引数の順序を保ったまま、同一の実ファイルを指すパスは最初の1件のみ残す。
+/// 収集に失敗したパスはエラーとして別に返し、残りのパスの収集は続ける。
+pub fn collect_all_files(paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<Error>) {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+
+    for path in paths {
+        match collect_text_files(path) {
+            Ok(found) => {
+                for file in found {
+                    let canonical = fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+                    if seen.insert(canonical) {
+                        files.push(file);
+                    }
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (files, errors)
+}
+
+// systemd-sysctl と同じ優先順位 (先頭ほど優先) で探索するディレクトリ
+const SYSCTL_D_DIRS: [&str; 4] = ["etc/sysctl.d", "run/sysctl.d", "usr/local/lib/sysctl.d", "usr/lib/sysctl.d"];
+
+/// `root` 配下の sysctl.d ディレクトリから `*.conf` を集め、systemd-sysctl が適用する順に返す。
+///
+/// ファイルはファイル名の辞書順に並べる。同名のファイルは優先度の高いディレクトリ
+/// (`/etc`, `/run`, `/usr/local/lib`, `/usr/lib` の順) のものだけを残し、
+/// `/dev/null` へのシンボリックリンクはマスクとして扱う。
+/// シンボリックリンクの絶対パスのリンク先は `root` 配下のパスとして解決し、返すパスはリンクをたどった先のパスにする。
+/// リンク先が存在しないファイルもそのまま返すため、読み込み時にエラーとなる。
+pub fn collect_sysctl_d_files(root: &Path) -> Result<Vec<PathBuf>> {
+    // ファイル名ごとの読み込むファイル。マスクされたファイルは None
+    let mut effective: BTreeMap<OsString, Option<PathBuf>> = BTreeMap::new();
+
+    for dir in SYSCTL_D_DIRS {
+        let dir = root.join(dir);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::io(Some(&dir), e)),
+        };
+        for entry in entries.filter_map(std::result::Result::ok) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "conf") && !effective.contains_key(&entry.file_name()) {
+                effective.insert(entry.file_name(), resolve_under_root(root, path)?);
+            }
+        }
+    }
+
+    // ディレクトリなど、ファイル以外のものは読み込まない
+    Ok(effective.into_values().flatten().filter(|path| !path.exists() || path.is_file()).collect())
+}
+
+// systemd と同じく、たどるシンボリックリンクの数の上限
+const MAX_SYMLINKS: usize = 40;
+
+// `root` を `/` とみなしてシンボリックリンクをたどる。`/dev/null` へのリンク (マスク) は None を返す
+fn resolve_under_root(root: &Path, mut path: PathBuf) -> Result<Option<PathBuf>> {
+    for _ in 0..MAX_SYMLINKS {
+        let target = match fs::read_link(&path) {
+            Ok(target) => target,
+            Err(_) => return Ok(Some(path)), // シンボリックリンクではない、または存在しない
+        };
+        if target == Path::new("/dev/null") {
+            return Ok(None);
+        }
+        path = match target.strip_prefix("/") {
+            Ok(relative) => root.join(relative),
+            Err(_) => path.parent().unwrap_or(root).join(target),
+        };
+    }
+    Err(Error::io(Some(&path), io::Error::other("シンボリックリンクが多すぎるか循環しています")))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    // テストごとに別の root を作り、`root` からの相対パスにファイルを書き出す
+    fn create_root(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("jic_test_02-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files.iter().map(|file| file.strip_prefix(root).unwrap().display().to_string()).collect()
+    }
+
+    #[test]
+    fn higher_priority_directories_override_same_names() {
+        let root = create_root(
+            "sysctl-precedence",
+            &[
+                ("usr/lib/sysctl.d/10-base.conf", ""),
+                ("usr/lib/sysctl.d/50-net.conf", ""),
+                ("usr/local/lib/sysctl.d/50-net.conf", ""),
+                ("run/sysctl.d/20-run.conf", ""),
+                ("etc/sysctl.d/50-net.conf", ""),
+                ("etc/sysctl.d/99-local.conf", ""),
+            ],
+        );
+
+        assert_eq!(
+            relative(&root, collect_sysctl_d_files(&root).unwrap()),
+            ["usr/lib/sysctl.d/10-base.conf", "run/sysctl.d/20-run.conf", "etc/sysctl.d/50-net.conf", "etc/sysctl.d/99-local.conf"]
+        );
+    }
+
+    #[test]
+    fn only_conf_files_are_read() {
+        let root = create_root(
+            "sysctl-filter",
+            &[("etc/sysctl.d/10-a.conf", ""), ("etc/sysctl.d/README", ""), ("etc/sysctl.d/20-b.conf.bak", ""), ("etc/sysctl.d/30-dir.conf/x", "")],
+        );
+
+        assert_eq!(relative(&root, collect_sysctl_d_files(&root).unwrap()), ["etc/sysctl.d/10-a.conf"]);
+    }
+
+    #[test]
+    fn links_to_dev_null_mask_lower_priority_files() {
+        let root = create_root("sysctl-mask", &[("usr/lib/sysctl.d/50-net.conf", ""), ("usr/lib/sysctl.d/60-other.conf", "")]);
+        fs::create_dir_all(root.join("etc/sysctl.d")).unwrap();
+        symlink("/dev/null", root.join("etc/sysctl.d/50-net.conf")).unwrap();
+
+        assert_eq!(relative(&root, collect_sysctl_d_files(&root).unwrap()), ["usr/lib/sysctl.d/60-other.conf"]);
+    }
+
+    #[test]
+    fn symlinks_are_resolved_under_root() {
+        let root = create_root("sysctl-symlink", &[("opt/sysctl/net.conf", ""), ("opt/sysctl/vm.conf", "")]);
+        fs::create_dir_all(root.join("etc/sysctl.d")).unwrap();
+        symlink("/opt/sysctl/net.conf", root.join("etc/sysctl.d/10-net.conf")).unwrap();
+        symlink("../../opt/sysctl/vm.conf", root.join("etc/sysctl.d/20-vm.conf")).unwrap();
+        // リンク先が存在しないファイルはマスクとして扱わず、読み込み時のエラーにする
+        symlink("/opt/sysctl/missing.conf", root.join("etc/sysctl.d/30-missing.conf")).unwrap();
+
+        let files = collect_sysctl_d_files(&root).unwrap();
+        assert_eq!(files, [root.join("opt/sysctl/net.conf"), root.join("etc/sysctl.d/../../opt/sysctl/vm.conf"), root.join("opt/sysctl/missing.conf")]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::parser::ParsedConfig;
+use crate::schema::{split_compound, FieldSchema, Presence, Schema};
+use crate::value::{parse_scalar, ConfigValue};
+
+/// 複数の設定からスキーマの下書きを推定する。
+///
+/// すべての設定のキーパスを合わせ、各キーの型を値から `bool`, `int`, `float`, `string` のいずれかに推定する。
+/// 設定によって型が異なる場合、`int` と `float` なら `float`、それ以外は `string` とし、
+/// ある設定では値で別の設定ではセクションのキーは `map` とする。
+/// インデックス付きのキーから組み立てたリストは `list<int>` のように、マップのリストは `servers.*.host` のように推定する。
+/// すべての設定 (`*` のキーではすべての要素) に現れるキーは `required`、それ以外は `optional` とする。
+pub fn infer_schema(configs: &[ParsedConfig]) -> Schema {
+    let mut inference = Inference::default();
+    for config in configs {
+        inference.walk("", &config.values);
+    }
+
+    inference
+        .fields
+        .iter()
+        .map(|(key, (value_type, count))| {
+            // 値とセクションが混在するキーは、セクションとして現れた回数も合わせて数える
+            let (value_type, count) = match inference.sections.get(key) {
+                Some(sections) => ("map".to_string(), count + sections),
+                None => (value_type.clone(), *count),
+            };
+            // `*` の配下のキーは、その `*` に一致する要素の数と比べる
+            let scopes = match key.rfind(".*") {
+                Some(end) => inference.elements[&key[..end + 2]],
+                None => configs.len(),
+            };
+            let presence = if count == scopes { Presence::Required } else { Presence::Optional };
+            (key.clone(), FieldSchema { value_type, constraints: Vec::new(), presence, default: None, description: None, example: None })
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Inference {
+    // キーパスごとの推定した型と、キーが現れた回数
+    fields: HashMap<String, (String, usize)>,
+    // 値ではなくセクションとして現れたキーパスと、その回数
+    sections: HashMap<String, usize>,
+    // `*` で終わるキーパスごとの、一致したリストの要素の数
+    elements: HashMap<String, usize>,
+}
+
+impl Inference {
+    fn walk(&mut self, prefix: &str, map: &HashMap<String, ConfigValue>) {
+        for (segment, value) in map {
+            let key = if prefix.is_empty() { segment.clone() } else { format!("{}.{}", prefix, segment) };
+            match value {
+                ConfigValue::Map(children) => {
+                    self.walk(&key, children);
+                    *self.sections.entry(key).or_default() += 1;
+                }
+                ConfigValue::List(items) => {
+                    let pattern = format!("{}.*", key);
+                    let mut element_type = None;
+                    for item in items {
+                        if let ConfigValue::Map(children) = item {
+                            *self.elements.entry(pattern.clone()).or_default() += 1;
+                            self.walk(&pattern, children);
+                        } else {
+                            element_type = Some(unify(element_type.as_deref(), scalar_type(item)));
+                        }
+                    }
+                    if let Some(element_type) = element_type {
+                        self.observe(key, format!("list<{}>", element_type));
+                    }
+                }
+                value => self.observe(key, scalar_type(value).to_string()),
+            }
+        }
+    }
+
+    fn observe(&mut self, key: String, value_type: String) {
+        let (current, count) = self.fields.entry(key).or_insert_with(|| (value_type.clone(), 0));
+        *current = unify(Some(current.as_str()), &value_type);
+        *count += 1;
+    }
+}
+
+// 値の文字列から型を推定する。`--detect-types` と同じ規則で整数と浮動小数点数を判定する
+fn scalar_type(value: &ConfigValue) -> &'static str {
+    match value {
+        ConfigValue::String(s) => match parse_scalar(s, true) {
+            value @ (ConfigValue::Int(_) | ConfigValue::Float(_)) => value.type_name(),
+            _ => "string",
+        },
+        value => value.type_name(),
+    }
+}
+
+// 異なる値から推定した2つの型を、どちらの値も受け入れる型にまとめる
+fn unify(current: Option<&str>, value_type: &str) -> String {
+    let Some(current) = current else {
+        return value_type.to_string();
+    };
+    match (current, value_type) {
+        (a, b) if a == b => a.to_string(),
+        ("int", "float") | ("float", "int") => "float".to_string(),
+        (a, b) => match (split_compound(a), split_compound(b)) {
+            (Some(("list", a)), Some(("list", b))) => format!("list<{}>", unify(Some(a[0]), b[0])),
+            _ => "string".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parser::{parse_config_str, ParseOptions};
+
+    fn infer(sources: &[&str]) -> Schema {
+        let configs: Vec<ParsedConfig> =
+            sources.iter().map(|source| parse_config_str(source, Path::new("test.conf"), &ParseOptions::default())).collect();
+        infer_schema(&configs)
+    }
+
+    fn field(schema: &Schema, key: &str) -> (String, Presence) {
+        (schema[key].value_type.clone(), schema[key].presence)
+    }
+
+    #[test]
+    fn types_are_unified_across_configs() {
+        let schema = infer(&["a = 1\nb = 1\nc = x\nd = 1\nports.0 = 80\n", "a = 1.5\nb = on\nc.0 = 1\nd.e = 1\nports.0 = 0.5\n"]);
+
+        assert_eq!(field(&schema, "a"), ("float".to_string(), Presence::Required));
+        assert_eq!(field(&schema, "b"), ("string".to_string(), Presence::Required));
+        // スカラー値と配列は string、値とセクションは map にする
+        assert_eq!(field(&schema, "c"), ("string".to_string(), Presence::Required));
+        assert_eq!(field(&schema, "d"), ("map".to_string(), Presence::Required));
+        assert_eq!(field(&schema, "d.e"), ("int".to_string(), Presence::Optional));
+        assert_eq!(field(&schema, "ports"), ("list<float>".to_string(), Presence::Required));
+    }
+
+    #[test]
+    fn keys_in_some_configs_are_optional() {
+        let schema = infer(&["debug = true\nlog.file = a\n", "debug = false\n"]);
+
+        assert_eq!(field(&schema, "debug"), ("bool".to_string(), Presence::Required));
+        // セクションのみのキーは宣言しない
+        assert!(!schema.contains_key("log"));
+        assert_eq!(field(&schema, "log.file"), ("string".to_string(), Presence::Optional));
+    }
+
+    #[test]
+    fn keys_under_list_elements_are_counted_per_element() {
+        let schema = infer(&[
+            "servers.0.host = a\nservers.0.port = 80\nservers.1.host = b\n",
+            "servers.0.host = c\nservers.0.port = 81\nservers.0.tags.0.name = x\nservers.0.tags.1.name = y\nservers.0.tags.1.color = red\n",
+        ]);
+
+        assert_eq!(field(&schema, "servers.*.host"), ("string".to_string(), Presence::Required));
+        assert_eq!(field(&schema, "servers.*.port"), ("int".to_string(), Presence::Optional));
+        // ネストした配列の要素は、最も内側の `*` に一致した要素の数と比べる
+        assert_eq!(field(&schema, "servers.*.tags.*.name"), ("string".to_string(), Presence::Required));
+        assert_eq!(field(&schema, "servers.*.tags.*.color"), ("string".to_string(), Presence::Optional));
+        assert!(!schema.contains_key("servers"));
+    }
+}
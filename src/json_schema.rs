@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::parser::Span;
+use crate::schema::{convert, element_type, raw_text, split_compound, violated_constraint, Constraint, FieldSchema, Presence, Schema};
+use crate::value::{value_as_json, ConfigValue};
+
+// `$ref` とプロパティのネストを辿る深さの上限。循環した参照を検出するために使う
+const MAX_DEPTH: usize = 64;
+
+/// JSON Schema を文字列から読み込み、スキーマに変換する。
+///
+/// `properties` のネストはドット区切りのキーに、`additionalProperties` と配列の `items` は `*` のキーに変換する。
+/// 対応するキーワードは `type`, `properties`, `required`, `additionalProperties`, `items`, `prefixItems`,
+/// `enum`, `pattern`, `minimum`, `maximum`, `minLength`, `maxLength`, `default`, `description`, `examples` (最初の値のみ) と、
+/// 文書内を指す `$ref`。
+/// 必須のキーは、親のオブジェクトもすべて必須の場合 (`*` のキーの配下ではその要素より下のオブジェクトがすべて必須の場合)
+/// にのみ `required` として扱う。
+pub fn parse_json_schema_str(source: &str) -> Result<Schema> {
+    let root: Value = serde_json::from_str(source).map_err(|e| Error::SchemaSyntax {
+        path: None,
+        span: Some(Span { file: 0, line: e.line(), start: e.column().max(1), end: e.column().max(1) + 1 }),
+        message: format!("JSON Schema を解析できません: {}", e),
+    })?;
+
+    let mut converter = Converter { root: &root, schema: HashMap::new() };
+    let root_schema = converter.resolve(&root, "#", 0)?;
+    if schema_type(root_schema)? != Some("object") && root_schema.get("properties").is_none() {
+        return Err(schema_error("#", "最上位は type が object のスキーマにしてください"));
+    }
+    converter.object("", root_schema, "#", true, 0)?;
+    Ok(converter.schema)
+}
+
+// 入力が JSON Schema かどうかを内容から判定する
+pub(crate) fn looks_like_json(source: &str) -> bool {
+    source.trim_start().starts_with('{')
+}
+
+fn schema_error(pointer: &str, message: impl Into<String>) -> Error {
+    Error::SchemaSyntax { path: None, span: None, message: format!("{} ({})", message.into(), pointer) }
+}
+
+// `type` を返す。`["string", "null"]` のような配列の場合は null 以外の最初の型を使う
+fn schema_type(node: &Value) -> Result<Option<&str>> {
+    match node.get("type") {
+        None => Ok(None),
+        Some(Value::String(t)) => Ok(Some(t)),
+        Some(Value::Array(types)) => Ok(types.iter().filter_map(Value::as_str).find(|t| *t != "null")),
+        Some(_) => Err(schema_error("type", "type には文字列または文字列の配列を指定してください")),
+    }
+}
+
+// `examples` の値をスキーマの注釈に書く文字列にする
+fn example_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(example_text).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+fn config_value(value: &Value) -> Option<ConfigValue> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(ConfigValue::Bool(*b)),
+        Value::Number(n) => Some(n.as_i64().map(ConfigValue::Int).unwrap_or_else(|| ConfigValue::Float(n.as_f64().unwrap_or_default()))),
+        Value::String(s) => Some(ConfigValue::String(s.clone())),
+        Value::Array(items) => items.iter().map(config_value).collect::<Option<Vec<_>>>().map(ConfigValue::List),
+        Value::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| Some((key.clone(), config_value(value)?)))
+            .collect::<Option<HashMap<_, _>>>()
+            .map(ConfigValue::Map),
+    }
+}
+
+// JSON の値を型 `value_type` の値に変換する。型と一致しない場合は None を返す
+fn typed_value(value: &Value, value_type: &str) -> Option<ConfigValue> {
+    if let Some((kind, elements)) = split_compound(value_type) {
+        let items = value.as_array()?;
+        if kind == "tuple" && items.len() != elements.len() {
+            return None;
+        }
+        return items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| typed_value(item, element_type(value_type, i)?))
+            .collect::<Option<Vec<_>>>()
+            .map(ConfigValue::List);
+    }
+    match (value_type, value) {
+        ("string", Value::String(s)) => Some(ConfigValue::String(s.clone())),
+        ("bool", Value::Bool(b)) => Some(ConfigValue::Bool(*b)),
+        ("int", Value::Number(n)) => n.as_i64().map(ConfigValue::Int),
+        // `float` の既定値は整数で書かれていても浮動小数点数として扱う
+        ("float", Value::Number(n)) => n.as_f64().map(ConfigValue::Float),
+        ("list", Value::Array(_)) | ("map", Value::Object(_)) => config_value(value),
+        _ => None,
+    }
+}
+
+// `default` を型に合わせて変換し、制約を満たすか確かめる
+fn default_value(value: &Value, value_type: &str, constraints: &[Constraint], pointer: &str) -> Result<ConfigValue> {
+    let default = typed_value(value, value_type).ok_or_else(|| schema_error(pointer, format!("既定値 {} は型 '{}' として解釈できません", value, value_type)))?;
+    if let Some(constraint) = violated_constraint(constraints, &default) {
+        return Err(schema_error(pointer, format!("既定値 {} が制約 {} を満たしません", value, constraint)));
+    }
+    Ok(default)
+}
+
+struct Converter<'a> {
+    root: &'a Value,
+    schema: Schema,
+}
+
+impl<'a> Converter<'a> {
+    // `$ref` が指す文書内のスキーマを返す
+    fn resolve(&self, node: &'a Value, pointer: &str, depth: usize) -> Result<&'a Value> {
+        match node.get("$ref").and_then(Value::as_str) {
+            None => Ok(node),
+            Some(_) if depth >= MAX_DEPTH => Err(schema_error(pointer, "$ref が循環しています")),
+            Some(reference) => {
+                let target = reference
+                    .strip_prefix('#')
+                    .and_then(|path| self.root.pointer(path))
+                    .ok_or_else(|| schema_error(pointer, format!("$ref '{}' を解決できません (文書内の参照のみ使用できます)", reference)))?;
+                self.resolve(target, reference, depth + 1)
+            }
+        }
+    }
+
+    fn object(&mut self, prefix: &str, node: &'a Value, pointer: &str, required: bool, depth: usize) -> Result<()> {
+        let required_names: Vec<&str> =
+            node.get("required").and_then(Value::as_array).map(|names| names.iter().filter_map(Value::as_str).collect()).unwrap_or_default();
+
+        if let Some(properties) = node.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+                let property_pointer = format!("{}/properties/{}", pointer, name);
+                let property_required = required && required_names.contains(&name.as_str());
+                self.property(&key, property, &property_pointer, property_required, depth)?;
+            }
+        }
+        if let Some(additional) = node.get("additionalProperties").filter(|value| value.is_object()) {
+            let key = if prefix.is_empty() { "*".to_string() } else { format!("{}.*", prefix) };
+            // ワイルドカードのキーは存在するキーごとに検証されるため、その配下の required はそのまま適用できる
+            self.property(&key, additional, &format!("{}/additionalProperties", pointer), true, depth)?;
+        }
+        Ok(())
+    }
+
+    fn property(&mut self, key: &str, node: &'a Value, pointer: &str, required: bool, depth: usize) -> Result<()> {
+        let node = self.resolve(node, pointer, depth)?;
+        let presence = if required { Presence::Required } else { Presence::Optional };
+
+        // 配列では要素のスキーマの制約を各要素に適用する
+        let mut constraint_node = node;
+        let value_type = match schema_type(node)? {
+            Some("object") => "map".to_string(),
+            None if node.get("properties").is_some() => "map".to_string(),
+            Some("array") => {
+                let (value_type, items) = self.array_type(key, node, pointer, depth)?;
+                constraint_node = items.unwrap_or(node);
+                value_type
+            }
+            Some(t) => scalar_type(t).ok_or_else(|| schema_error(pointer, format!("type '{}' には対応していません", t)))?.to_string(),
+            None if node.get("enum").is_some() || node.get("pattern").is_some() => "string".to_string(),
+            // 型の指定がないキーは検証しない
+            None => return Ok(()),
+        };
+
+        if value_type == "map" {
+            self.object(key, node, pointer, required, depth + 1)?;
+        }
+        let constraints = constraints(constraint_node, element_type(&value_type, 0).unwrap_or(&value_type), pointer)?;
+        let default = match node.get("default") {
+            Some(value) if !required && !value.is_null() => Some(default_value(value, &value_type, &constraints, &format!("{}/default", pointer))?),
+            _ => None,
+        };
+        let description = node.get("description").and_then(Value::as_str).map(str::to_string);
+        let example = node.get("examples").and_then(Value::as_array).and_then(|examples| examples.first()).map(example_text);
+        self.schema.insert(key.to_string(), FieldSchema { value_type, constraints, presence, default, description, example });
+        Ok(())
+    }
+
+    // 配列の型と、要素がスカラー型の場合はその要素のスキーマを返す。
+    // 要素がオブジェクトの場合は `key.*.name` のキーとして要素のプロパティを登録する
+    fn array_type(&mut self, key: &str, node: &'a Value, pointer: &str, depth: usize) -> Result<(String, Option<&'a Value>)> {
+        if let Some(prefix_items) = node.get("prefixItems").and_then(Value::as_array) {
+            let elements = prefix_items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let item_pointer = format!("{}/prefixItems/{}", pointer, i);
+                    let item = self.resolve(item, &item_pointer, depth)?;
+                    schema_type(item)?
+                        .and_then(scalar_type)
+                        .ok_or_else(|| schema_error(&item_pointer, "prefixItems の要素にはスカラー型を指定してください"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok((format!("tuple<{}>", elements.join(",")), None));
+        }
+
+        let Some(items) = node.get("items") else {
+            return Ok(("list".to_string(), None));
+        };
+        let items_pointer = format!("{}/items", pointer);
+        let items = self.resolve(items, &items_pointer, depth)?;
+        match schema_type(items)? {
+            Some(t) if scalar_type(t).is_some() => Ok((format!("list<{}>", scalar_type(t).unwrap()), Some(items))),
+            Some("object") | None if items.get("properties").is_some() => {
+                self.object(&format!("{}.*", key), items, &items_pointer, true, depth + 1)?;
+                Ok(("list".to_string(), None))
+            }
+            _ => Ok(("list".to_string(), None)),
+        }
+    }
+}
+
+fn scalar_type(json_type: &str) -> Option<&'static str> {
+    match json_type {
+        "string" => Some("string"),
+        "boolean" => Some("bool"),
+        "integer" => Some("int"),
+        "number" => Some("float"),
+        _ => None,
+    }
+}
+
+// `value_type` は制約を適用する値 (配列の場合は要素) の型
+fn constraints(node: &Value, value_type: &str, pointer: &str) -> Result<Vec<Constraint>> {
+    let mut constraints = Vec::new();
+    if let Some(values) = node.get("enum").and_then(Value::as_array) {
+        // 値と同じ書式で比較できるように、列挙した値を型に合わせて変換してから文字列にする
+        let values = values
+            .iter()
+            .map(|value| {
+                let typed = match value {
+                    Value::String(s) if value_type == "string" => return Ok(s.clone()),
+                    // 型を省略した enum は string として扱うため、数値などはそのまま文字列にする
+                    value if value_type == "string" => return Ok(value.to_string()),
+                    Value::String(s) => convert(s, value_type),
+                    value => typed_value(value, value_type),
+                };
+                typed
+                    .filter(|typed| !typed.is_section())
+                    .map(|typed| raw_text(&typed))
+                    .ok_or_else(|| schema_error(pointer, format!("enum の値 {} は型 '{}' として解釈できません", value, value_type)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        constraints.push(Constraint::Enum(values));
+    }
+    if let Some(pattern) = node.get("pattern").and_then(Value::as_str) {
+        let regex = Regex::new(pattern).map_err(|e| schema_error(pointer, format!("pattern '{}' が不正です: {}", pattern, e)))?;
+        constraints.push(Constraint::Pattern(regex));
+    }
+    let (min, max) = (node.get("minimum").and_then(Value::as_f64), node.get("maximum").and_then(Value::as_f64));
+    if min.is_some() || max.is_some() {
+        constraints.push(Constraint::Range { min, max });
+    }
+    let length = |name: &str| node.get(name).and_then(Value::as_u64).map(|n| n as usize);
+    let (min, max) = (length("minLength"), length("maxLength"));
+    if min.is_some() || max.is_some() {
+        constraints.push(Constraint::Length { min, max });
+    }
+    Ok(constraints)
+}
+
+/// スキーマを、[`format_as_json`](crate::format_as_json) が出力するオブジェクトを表す
+/// Draft 2020-12 の JSON Schema に変換する。
+///
+/// ドット区切りのキーはネストした `properties` に、`*` の区切りは `additionalProperties`
+/// (親が配列の場合は `items`) に変換する。`**` を含むキーは JSON Schema で表せないため出力しない。
+/// 時間は秒数、バイト数は整数として表す。
+///
+/// `map` 以外の型のキー (`*` の場合は要素の型を指定していない `list`) の配下にキーが宣言されている場合は
+/// JSON Schema で表せないため [`Error::SchemaSyntax`] を返す。
+pub fn to_json_schema(schema: &Schema) -> Result<Value> {
+    let mut root = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+    });
+    let mut keys: Vec<&String> = schema.keys().filter(|key| !key.split('.').any(|segment| segment == "**")).collect();
+    // 親のキーが先に並ぶため、子のキーは親の型が確定してから追加される
+    keys.sort();
+    for key in keys {
+        let segments: Vec<&str> = key.split('.').collect();
+        insert_field(&mut root, &segments, 0, &schema[key])?;
+    }
+    Ok(root)
+}
+
+// `segments[index]` の区切りを `node` の配下に追加する
+fn insert_field(node: &mut Value, segments: &[&str], index: usize, field: &FieldSchema) -> Result<()> {
+    let (segment, rest) = (segments[index], &segments[index + 1..]);
+    let node_type = node.get("type").and_then(Value::as_str).unwrap_or_default().to_string();
+    // 配下にキーを持てるのはオブジェクトと、要素の型を指定していない配列の要素 (`*`) のみ
+    let accepts_children = match node_type.as_str() {
+        "object" => true,
+        "array" => segment == "*" && node.get("prefixItems").is_none() && node.get("items").is_none_or(|items| items.get("type") == Some(&json!("object"))),
+        _ => false,
+    };
+    if !accepts_children {
+        return Err(Error::SchemaSyntax {
+            path: None,
+            span: None,
+            message: format!(
+                "キー '{}' は JSON Schema に変換できません (キー '{}' の型 '{}' は配下にキーを持てません)",
+                segments.join("."),
+                segments[..index].join("."),
+                node_type,
+            ),
+        });
+    }
+
+    let node = node.as_object_mut().unwrap();
+    let child = if segment == "*" {
+        let container = if node_type == "array" { "items" } else { "additionalProperties" };
+        node.entry(container).or_insert_with(|| json!({ "type": "object" }))
+    } else {
+        // 必須のキーを含むセクションはそれ自体も必須とする。ワイルドカードより上のセクションは除く
+        if field.presence == Presence::Required && !rest.contains(&"*") {
+            let required = node.entry("required").or_insert_with(|| json!([])).as_array_mut().unwrap();
+            if !required.contains(&json!(segment)) {
+                required.push(json!(segment));
+            }
+        }
+        let properties = node.entry("properties").or_insert_with(|| json!({}));
+        properties.as_object_mut().unwrap().entry(segment.to_string()).or_insert_with(|| json!({ "type": "object" }))
+    };
+
+    if rest.is_empty() {
+        let child = child.as_object_mut().unwrap();
+        for (name, value) in field_schema(field) {
+            child.insert(name, value);
+        }
+        Ok(())
+    } else {
+        insert_field(child, segments, index + 1, field)
+    }
+}
+
+fn field_schema(field: &FieldSchema) -> serde_json::Map<String, Value> {
+    let mut node = match split_compound(&field.value_type) {
+        Some(("list", elements)) => json!({ "type": "array", "items": type_schema(elements[0], &field.constraints) }),
+        Some((_, elements)) => json!({
+            "type": "array",
+            "prefixItems": elements.iter().map(|element| type_schema(element, &field.constraints)).collect::<Vec<_>>(),
+            "items": false,
+            "minItems": elements.len(),
+        }),
+        None if field.value_type == "list" => json!({ "type": "array" }),
+        None => type_schema(&field.value_type, &field.constraints),
+    };
+    if let Some(default) = &field.default {
+        node["default"] = value_as_json(default);
+    }
+    if let Some(description) = &field.description {
+        node["description"] = json!(description);
+    }
+    if let Some(example) = &field.example {
+        let value = convert(example, &field.value_type).map(|value| value_as_json(&value)).unwrap_or_else(|| json!(example));
+        node["examples"] = json!([value]);
+    }
+    match node {
+        Value::Object(map) => map,
+        _ => unreachable!(),
+    }
+}
+
+fn type_schema(value_type: &str, constraints: &[Constraint]) -> Value {
+    let mut node = match value_type {
+        "bool" => json!({ "type": "boolean" }),
+        "int" => json!({ "type": "integer" }),
+        "float" => json!({ "type": "number" }),
+        "duration" => json!({ "type": "number", "minimum": 0 }),
+        "size" => json!({ "type": "integer", "minimum": 0 }),
+        "map" => json!({ "type": "object" }),
+        _ => json!({ "type": "string" }),
+    };
+    for constraint in constraints {
+        match constraint {
+            Constraint::Enum(values) => node["enum"] = json!(values),
+            Constraint::Pattern(regex) => node["pattern"] = json!(regex.as_str()),
+            Constraint::Range { min, max } => {
+                if let Some(min) = min {
+                    node["minimum"] = number(*min);
+                }
+                if let Some(max) = max {
+                    node["maximum"] = number(*max);
+                }
+            }
+            Constraint::Length { min, max } => {
+                if let Some(min) = min {
+                    node["minLength"] = json!(min);
+                }
+                if let Some(max) = max {
+                    node["maxLength"] = json!(max);
+                }
+            }
+        }
+    }
+    node
+}
+
+// 整数で表せる値は整数として出力する
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 { json!(value as i64) } else { json!(value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::parser::{parse_config_str, ParseOptions};
+    use crate::schema::{apply_schema_types, parse_schema_str, validate_config};
+
+    fn import(source: Value) -> Schema {
+        parse_json_schema_str(&source.to_string()).unwrap()
+    }
+
+    fn export(source: &str) -> Result<Value> {
+        to_json_schema(&parse_schema_str(source).unwrap())
+    }
+
+    fn codes(schema: &Schema, config: &str) -> Vec<&'static str> {
+        let mut parsed = parse_config_str(config, Path::new("test.conf"), &ParseOptions::default());
+        apply_schema_types(&mut parsed, schema);
+        validate_config(&parsed, schema).into_iter().map(|diagnostic| diagnostic.code).collect()
+    }
+
+    #[test]
+    fn required_applies_only_under_required_parents() {
+        let schema = import(json!({
+            "type": "object",
+            "required": ["db"],
+            "properties": {
+                "db": { "type": "object", "required": ["host"], "properties": { "host": { "type": "string" }, "port": { "type": "integer" } } },
+                "log": { "type": "object", "required": ["file"], "properties": { "file": { "type": "string" } } },
+            },
+        }));
+
+        assert_eq!(schema["db"].presence, Presence::Required);
+        assert_eq!(schema["db.host"].presence, Presence::Required);
+        assert_eq!(schema["db.port"].presence, Presence::Optional);
+        // 親の log が省略可能なため、log.file も省略可能になる
+        assert_eq!(schema["log.file"].presence, Presence::Optional);
+    }
+
+    #[test]
+    fn refs_and_additional_properties_become_keys() {
+        let schema = import(json!({
+            "type": "object",
+            "$defs": { "server": { "type": "object", "required": ["host"], "properties": { "host": { "type": "string" } } } },
+            "properties": {
+                "servers": { "type": "object", "additionalProperties": { "$ref": "#/$defs/server" } },
+                "backup": { "$ref": "#/$defs/server" },
+            },
+        }));
+
+        assert_eq!(schema["servers.*"].value_type, "map");
+        assert_eq!(schema["servers.*.host"].presence, Presence::Required);
+        assert_eq!(schema["backup.host"].value_type, "string");
+
+        let looped = json!({ "type": "object", "properties": { "a": { "$ref": "#/properties/a" } } });
+        assert!(parse_json_schema_str(&looped.to_string()).is_err());
+    }
+
+    #[test]
+    fn arrays_become_lists_and_tuples() {
+        let schema = import(json!({
+            "type": "object",
+            "properties": {
+                "range": { "type": "array", "prefixItems": [{ "type": "integer" }, { "type": "integer" }] },
+                "names": { "type": "array", "items": { "type": "string", "minLength": 1 } },
+                "servers": { "type": "array", "items": { "type": "object", "properties": { "host": { "type": "string" } } } },
+            },
+        }));
+
+        assert_eq!(schema["range"].value_type, "tuple<int,int>");
+        assert_eq!(schema["names"].value_type, "list<string>");
+        assert!(matches!(schema["names"].constraints[..], [Constraint::Length { min: Some(1), max: None }]));
+        assert_eq!(schema["servers"].value_type, "list");
+        assert_eq!(schema["servers.*.host"].value_type, "string");
+    }
+
+    #[test]
+    fn defaults_are_converted_to_the_declared_type() {
+        let schema = import(json!({
+            "type": "object",
+            "properties": {
+                "ratio": { "type": "number", "default": 1 },
+                "ports": { "type": "array", "items": { "type": "integer" }, "default": [80, 443] },
+            },
+        }));
+        assert_eq!(schema["ratio"].default, Some(ConfigValue::Float(1.0)));
+        assert_eq!(schema["ports"].default, Some(ConfigValue::List(vec![ConfigValue::Int(80), ConfigValue::Int(443)])));
+
+        // export-json-schema の出力を読み戻しても既定値が型と一致する
+        let exported = export("timeout -> duration = 10s\nratio -> float = 2\n").unwrap();
+        let schema = import(exported);
+        assert_eq!(schema["timeout"].default, Some(ConfigValue::Float(10.0)));
+        assert_eq!(schema["ratio"].default, Some(ConfigValue::Float(2.0)));
+    }
+
+    #[test]
+    fn defaults_that_do_not_match_are_rejected() {
+        for property in [
+            json!({ "type": "integer", "default": "x" }),
+            json!({ "type": "integer", "default": 1.5 }),
+            json!({ "type": "string", "default": 1 }),
+            json!({ "type": "integer", "maximum": 10, "default": 20 }),
+            json!({ "type": "array", "prefixItems": [{ "type": "integer" }], "default": [1, 2] }),
+        ] {
+            let source = json!({ "type": "object", "properties": { "a": property } });
+            assert!(matches!(parse_json_schema_str(&source.to_string()), Err(Error::SchemaSyntax { .. })), "{}", source);
+        }
+    }
+
+    #[test]
+    fn enums_on_numbers_and_bools_are_enforced() {
+        let schema = import(json!({
+            "type": "object",
+            "properties": {
+                "level": { "type": "integer", "enum": [1, 2, 3] },
+                "ratio": { "type": "number", "enum": [0.5, 1.0] },
+                "debug": { "type": "boolean", "enum": [false] },
+            },
+        }));
+
+        assert_eq!(codes(&schema, "level = 2\nratio = 1\ndebug = false\n"), Vec::<&str>::new());
+        assert_eq!(codes(&schema, "level = 7\nratio = 0.75\ndebug = true\n"), ["invalid-enum", "invalid-enum", "invalid-enum"]);
+
+        let source = json!({ "type": "object", "properties": { "level": { "type": "integer", "enum": [1, "high"] } } });
+        assert!(parse_json_schema_str(&source.to_string()).is_err());
+    }
+
+    #[test]
+    fn to_json_schema_nests_sections_and_wildcards() {
+        let exported = export("db.*.host -> string required\nservers -> list\nservers.* -> int\n").unwrap();
+
+        assert_eq!(exported["properties"]["db"]["additionalProperties"]["properties"]["host"]["type"], "string");
+        assert_eq!(exported["properties"]["servers"]["items"]["type"], "integer");
+    }
+
+    #[test]
+    fn to_json_schema_rejects_keys_under_non_object_types() {
+        for source in ["ports -> tuple<int,int>\nports.* -> int\n", "x -> string\nx.y -> int\n", "xs -> list<int>\nxs.* -> int\n"] {
+            assert!(matches!(export(source), Err(Error::SchemaSyntax { .. })), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! linuxのsysctl.confと同じ文法の設定ファイルを読み込み、スキーマで検証するライブラリ。
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use jic_test_02::{load_schema, parse_config_file, validate_config, ParseOptions};
+//!
+//! let schema = load_schema(Path::new("check.schema"))?;
+//! let parsed = parse_config_file(Path::new("input_files/test1.conf"), &ParseOptions::default())?;
+//! for diagnostic in parsed.diagnostics.iter().chain(&validate_config(&parsed, &schema)) {
+//!     eprintln!("{}", diagnostic.render(&parsed.files));
+//! }
+//! # Ok::<(), jic_test_02::Error>(())
+//! ```
+
+mod de;
+mod diagnostic;
+mod doc;
+mod error;
+mod files;
+mod infer;
+mod json_schema;
+mod parser;
+mod schema;
+mod ser;
+mod value;
+
+pub use de::{from_config, from_parsed, from_path, from_str, ConfigMapDeserializer};
+pub use diagnostic::{Diagnostic, Severity};
+pub use doc::{format_schema_html, format_schema_markdown};
+pub use error::{Error, Result};
+pub use files::{collect_all_files, collect_sysctl_d_files, collect_text_files};
+pub use infer::infer_schema;
+pub use json_schema::{parse_json_schema_str, to_json_schema};
+pub use parser::{
+    parse_config_file, parse_config_reader, parse_config_str, ConflictPolicy, DuplicatePolicy, EntrySpan,
+    ParseOptions, ParsedConfig, SourceFile, Span,
+};
+pub use schema::{
+    apply_schema_types, check_config, check_unknown_keys, fill_defaults, format_schema, load_schema, parse_schema_reader,
+    parse_schema_str, validate_config, Constraint, FieldSchema, Presence, Schema,
+};
+pub use ser::{to_string, to_string_with_options, to_writer, SerializeOptions};
+pub use value::{format_as_json, lookup_path, ConfigValue, LookupError};
@@ -1,169 +1,309 @@
-use std::collections::HashMap;
 use std::env;
-use std::fs;
-use std::io::{self, BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use regex::Regex;
-use lazy_static::lazy_static;
-use serde_json::json;
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum ConfigValue {
-    String(String),
-    Map(HashMap<String, ConfigValue>),
-    Bool(bool),
+use std::process::ExitCode;
+
+use jic_test_02::{
+    apply_schema_types, check_unknown_keys, collect_all_files, collect_sysctl_d_files, fill_defaults, format_as_json, format_schema,
+    format_schema_html, format_schema_markdown, infer_schema, load_schema, parse_config_file, to_json_schema, validate_config, ConflictPolicy, DuplicatePolicy, ParseOptions, ParsedConfig, Schema, Severity,
+};
+
+const EXIT_SUCCESS: u8 = 0;
+const EXIT_VALIDATION_WARNING: u8 = 1;
+const EXIT_VALIDATION_ERROR: u8 = 2;
+const EXIT_IO_ERROR: u8 = 3;
+const EXIT_SCHEMA_ERROR: u8 = 4;
+const EXIT_USAGE: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailOn {
+    Warning,
+    Error,
 }
 
-lazy_static! {
-    static ref CONFIG_REGEX: Regex = Regex::new(r"^\s*([a-zA-Z0-9._-]+)\s*=\s*(.+?)\s*$").unwrap();
-    static ref COMMENT_REGEX: Regex = Regex::new(r"^\s*#").unwrap();
-    static ref SCHEMA_REGEX: Regex = Regex::new(r"^\s*([a-zA-Z0-9._-]+)\s*->\s*(string|bool|map)\s*$").unwrap();
+#[derive(Debug)]
+struct Options {
+    fail_on: FailOn,
+    parse_options: ParseOptions,
+    schema_path: PathBuf,
+    config_paths: Vec<PathBuf>,
+    // 指定された場合は systemd-sysctl と同様に root 配下の sysctl.d を読み込んで1つの設定にまとめる
+    sysctl_d_root: Option<PathBuf>,
+    // 存在しないキーにスキーマの既定値を設定して出力する
+    fill_defaults: bool,
+    // 指定された場合はスキーマで宣言されていないキーをこの重大度で報告する
+    unknown_keys: Option<Severity>,
 }
 
-fn parse_config_file(file_path: &Path) -> io::Result<HashMap<String, ConfigValue>> {
-    let file = fs::File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut config = HashMap::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DocFormat {
+    Markdown,
+    Html,
+}
 
-    for line in reader.lines().flatten() {
-        let trimmed_line = line.trim();
-        if COMMENT_REGEX.is_match(trimmed_line) || trimmed_line.is_empty() {
-            continue; // コメント行・空行をスキップ
-        }
+#[derive(Debug)]
+enum Command {
+    // 設定ファイルを検証してJSONで出力する
+    Check(Options),
+    // スキーマファイルを JSON Schema に変換して出力する
+    ExportJsonSchema(PathBuf),
+    // 設定ファイルからスキーマの下書きを推定して出力する
+    Infer(Vec<PathBuf>),
+    // スキーマをリファレンスの文書にして出力する
+    Doc(DocFormat, PathBuf),
+}
 
-        if let Some(captures) = CONFIG_REGEX.captures(trimmed_line) {
-            let key = captures[1].to_string();
-            let raw_value = captures[2].trim().to_string();
-            let value = if raw_value.eq_ignore_ascii_case("true") || raw_value.eq_ignore_ascii_case("false") {
-                ConfigValue::Bool(raw_value.eq_ignore_ascii_case("true"))
-            } else {
-                ConfigValue::String(raw_value)
-            };
-            insert_config_value(&mut config, &key, value);
-        }
+fn parse_command(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("export-json-schema") => match &args[1..] {
+            [schema_path] => Ok(Command::ExportJsonSchema(PathBuf::from(schema_path))),
+            _ => Err("export-json-schema にはスキーマファイルを1つ指定してください".to_string()),
+        },
+        Some("infer") => match &args[1..] {
+            [] => Err("infer には設定ファイルまたはディレクトリを指定してください".to_string()),
+            paths => Ok(Command::Infer(paths.iter().map(PathBuf::from).collect())),
+        },
+        Some("doc") => match &args[1..] {
+            [schema_path] => Ok(Command::Doc(DocFormat::Markdown, PathBuf::from(schema_path))),
+            [format, schema_path] => match format.as_str() {
+                "--format=markdown" => Ok(Command::Doc(DocFormat::Markdown, PathBuf::from(schema_path))),
+                "--format=html" => Ok(Command::Doc(DocFormat::Html, PathBuf::from(schema_path))),
+                _ => Err(format!("--format には markdown または html を指定してください: {}", format)),
+            },
+            _ => Err("doc にはスキーマファイルを1つ指定してください".to_string()),
+        },
+        _ => parse_args(args).map(Command::Check),
     }
-
-    Ok(config)
 }
 
-fn insert_config_value(config: &mut HashMap<String, ConfigValue>, key: &str, value: ConfigValue) {
-    let keys: Vec<&str> = key.split('.').collect();
-    let mut map = config;
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut fail_on = FailOn::Error;
+    let mut parse_options = ParseOptions::default();
+    let mut systemd = false;
+    let mut fill_defaults = false;
+    let mut unknown_keys = None;
+    let mut root = PathBuf::from("/");
+    let mut positional = Vec::new();
 
-    for sub_key in &keys[..keys.len() - 1] {
-        map = map.entry(sub_key.to_string())
-            .or_insert_with(|| ConfigValue::Map(HashMap::new()))
-            .as_map_mut()
-            .expect("型の不一致");
+    for arg in args {
+        if let Some(value) = arg.strip_prefix("--fail-on=") {
+            fail_on = match value {
+                "warning" => FailOn::Warning,
+                "error" => FailOn::Error,
+                _ => return Err(format!("--fail-on には warning または error を指定してください: {}", value)),
+            };
+        } else if let Some(value) = arg.strip_prefix("--on-duplicate=") {
+            parse_options.duplicate_policy = match value {
+                "error" => DuplicatePolicy::Error,
+                "last-wins" => DuplicatePolicy::LastWins,
+                "first-wins" => DuplicatePolicy::FirstWins,
+                _ => return Err(format!("--on-duplicate には error, last-wins, first-wins のいずれかを指定してください: {}", value)),
+            };
+        } else if arg == "--systemd" {
+            systemd = true;
+        } else if let Some(value) = arg.strip_prefix("--root=") {
+            root = PathBuf::from(value);
+        } else if arg == "--strict-syntax" {
+            parse_options.strict_syntax = true;
+        } else if arg == "--detect-types" {
+            parse_options.detect_types = true;
+        } else if arg == "--fill-defaults" {
+            fill_defaults = true;
+        } else if let Some(value) = arg.strip_prefix("--unknown-keys=") {
+            unknown_keys = match value {
+                "warning" => Some(Severity::Warning),
+                "error" => Some(Severity::Error),
+                _ => return Err(format!("--unknown-keys には warning または error を指定してください: {}", value)),
+            };
+        } else if let Some(value) = arg.strip_prefix("--on-conflict=") {
+            parse_options.conflict_policy = match value {
+                "error" => ConflictPolicy::Error,
+                "last-wins" => ConflictPolicy::LastWins,
+                "keep-first" => ConflictPolicy::KeepFirst,
+                _ => return Err(format!("--on-conflict には error, last-wins, keep-first のいずれかを指定してください: {}", value)),
+            };
+        } else if arg.starts_with("--") {
+            return Err(format!("不明なオプションです: {}", arg));
+        } else {
+            positional.push(PathBuf::from(arg));
+        }
     }
 
-    map.insert(keys.last().unwrap().to_string(), value);
-}
-
-fn collect_text_files(path: &Path) -> io::Result<Vec<PathBuf>> {
-    if path.is_file() {
-        return Ok(vec![path.to_path_buf()]);
+    if positional.is_empty() {
+        return Err("スキーマファイルを指定してください".to_string());
+    }
+    let schema_path = positional.remove(0);
+    if systemd && !positional.is_empty() {
+        return Err("--systemd 指定時は設定ファイルを指定できません".to_string());
     }
-    if path.is_dir() {
-        return Ok(fs::read_dir(path)?
-            .filter_map(Result::ok)
-            .map(|entry| entry.path())
-            .filter(|p| p.is_file())
-            .collect());
+    if !systemd && positional.is_empty() {
+        return Err("設定ファイルまたはディレクトリを指定してください".to_string());
     }
-    Err(io::Error::new(io::ErrorKind::NotFound, "パスが見つかりません"))
+
+    Ok(Options {
+        fail_on,
+        parse_options,
+        schema_path,
+        config_paths: positional,
+        sysctl_d_root: systemd.then_some(root),
+        fill_defaults,
+        unknown_keys,
+    })
 }
 
-fn format_as_json(config: &HashMap<String, ConfigValue>) -> serde_json::Value {
-    let mut json_obj = serde_json::Map::new();
-    for (key, value) in config {
-        match value {
-            ConfigValue::String(s) => {
-                json_obj.insert(key.clone(), json!(s));
-            }
-            ConfigValue::Map(m) => {
-                json_obj.insert(key.clone(), format_as_json(m));
+fn run(options: &Options) -> u8 {
+    let schema = match load_schema(&options.schema_path) {
+        Ok(schema) => schema,
+        Err(e) => {
+            eprintln!("エラー: スキーマファイルの読み込みに失敗しました: {}", e);
+            return EXIT_SCHEMA_ERROR;
+        }
+    };
+
+    let mut io_failed = false;
+    let mut worst = None;
+
+    if let Some(root) = &options.sysctl_d_root {
+        println!("=== sysctl.d: {} ===", root.display());
+        let files = match collect_sysctl_d_files(root) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("エラー: ファイルの収集に失敗しました: {}", e);
+                return EXIT_IO_ERROR;
             }
-            ConfigValue::Bool(b) => {
-                json_obj.insert(key.clone(), json!(b));
+        };
+
+        let mut merged = ParsedConfig::default();
+        for file in files {
+            println!("--- {}", file.display());
+            if let Err(e) = merged.add_file(&file, &options.parse_options) {
+                io_failed = true;
+                eprintln!("エラー: ファイルの読み込みに失敗しました: {}", e);
             }
         }
-    }
-    serde_json::Value::Object(json_obj)
-}
+        report(merged, &schema, options, &mut worst);
+    } else {
+        let (files, errors) = collect_all_files(&options.config_paths);
+        for e in &errors {
+            eprintln!("エラー: ファイルの収集に失敗しました: {}", e);
+        }
+        io_failed = !errors.is_empty();
 
-fn validate_config(config: &HashMap<String, ConfigValue>, schema: &HashMap<String, String>) {
-    for (key, expected_type) in schema {
-        if let Some(value) = config.get(key) {
-            match (expected_type.as_str(), value) {
-                ("string", ConfigValue::String(_)) => (),
-                ("bool", ConfigValue::Bool(_)) => (),
-                ("map", ConfigValue::Map(_)) => (),
-                _ => {
-                    eprintln!("キー '{}' の値が期待される型 '{}' と一致しません", key, expected_type);
+        for file in files {
+            println!("=== ファイル: {} ===", file.display());
+            match parse_config_file(&file, &options.parse_options) {
+                Ok(parsed) => report(parsed, &schema, options, &mut worst),
+                Err(e) => {
+                    io_failed = true;
+                    eprintln!("エラー: ファイルの読み込みに失敗しました: {}", e);
                 }
             }
-        } else {
-            println!("警告: キー '{}' が存在しません", key);
         }
     }
-}
 
-fn load_schema(file_path: &Path) -> io::Result<HashMap<String, String>> {
-    let file = fs::File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut schema = HashMap::new();
+    match (io_failed, worst, options.fail_on) {
+        (true, _, _) => EXIT_IO_ERROR,
+        (false, Some(Severity::Error), _) => EXIT_VALIDATION_ERROR,
+        (false, Some(Severity::Warning), FailOn::Warning) => EXIT_VALIDATION_WARNING,
+        _ => EXIT_SUCCESS,
+    }
+}
 
-    for line in reader.lines().flatten() {
-        let trimmed_line = line.trim();
-        if COMMENT_REGEX.is_match(trimmed_line) || trimmed_line.is_empty() {
-            continue; // コメント行・空行をスキップ
+fn export_json_schema(schema_path: &Path) -> u8 {
+    let schema = match load_schema(schema_path) {
+        Ok(schema) => schema,
+        Err(e) => {
+            eprintln!("エラー: スキーマファイルの読み込みに失敗しました: {}", e);
+            return EXIT_SCHEMA_ERROR;
         }
-
-        if let Some(captures) = SCHEMA_REGEX.captures(trimmed_line) {
-            let key = captures[1].to_string();
-            let value_type = captures[2].trim().to_string();
-            schema.insert(key, value_type);
+    };
+    match to_json_schema(&schema) {
+        Ok(json_schema) => {
+            println!("{}", serde_json::to_string_pretty(&json_schema).unwrap());
+            EXIT_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("エラー: {}", e);
+            EXIT_SCHEMA_ERROR
         }
     }
+}
 
-    Ok(schema)
+fn doc(format: DocFormat, schema_path: &Path) -> u8 {
+    let schema = match load_schema(schema_path) {
+        Ok(schema) => schema,
+        Err(e) => {
+            eprintln!("エラー: スキーマファイルの読み込みに失敗しました: {}", e);
+            return EXIT_SCHEMA_ERROR;
+        }
+    };
+    let title = schema_path.file_stem().unwrap_or(schema_path.as_os_str()).to_string_lossy();
+    match format {
+        DocFormat::Markdown => print!("{}", format_schema_markdown(&schema, &title)),
+        DocFormat::Html => print!("{}", format_schema_html(&schema, &title)),
+    }
+    EXIT_SUCCESS
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("使用方法: {} <スキーマファイル> <設定ファイルまたはディレクトリ>", args[0]);
-        std::process::exit(1);
+fn infer(paths: &[PathBuf]) -> u8 {
+    let (files, errors) = collect_all_files(paths);
+    for e in &errors {
+        eprintln!("エラー: ファイルの収集に失敗しました: {}", e);
     }
+    let mut io_failed = !errors.is_empty();
 
-    let schema_path = Path::new(&args[1]);
-    let config_path = Path::new(&args[2]);
-
-    match load_schema(schema_path) {
-        Ok(schema) => match collect_text_files(config_path) {
-            Ok(files) => files.iter().for_each(|file| {
-                println!("=== ファイル: {} ===", file.display());
-                match parse_config_file(file) {
-                    Ok(config) => {
-                        validate_config(&config, &schema);
-                        println!("{}", serde_json::to_string_pretty(&format_as_json(&config)).unwrap());
-                    }
-                    Err(e) => eprintln!("エラー: ファイルの読み込みに失敗しました: {} ({})", e, file.display()),
+    let mut configs = Vec::new();
+    for file in files {
+        match parse_config_file(&file, &ParseOptions::default()) {
+            Ok(parsed) => {
+                for diagnostic in &parsed.diagnostics {
+                    eprintln!("{}", diagnostic.render(&parsed.files));
                 }
-            }),
-            Err(e) => eprintln!("エラー: ファイルの収集に失敗しました: {} ({})", e, config_path.display()),
-        },
-        Err(e) => eprintln!("エラー: スキーマファイルの読み込みに失敗しました: {} ({})", e, schema_path.display()),
-    };
+                configs.push(parsed);
+            }
+            Err(e) => {
+                io_failed = true;
+                eprintln!("エラー: ファイルの読み込みに失敗しました: {}", e);
+            }
+        }
+    }
+
+    println!("# {} 個のファイルから推定したスキーマ", configs.len());
+    print!("{}", format_schema(&infer_schema(&configs)));
+    if io_failed { EXIT_IO_ERROR } else { EXIT_SUCCESS }
 }
 
-impl ConfigValue {
-    fn as_map_mut(&mut self) -> Option<&mut HashMap<String, ConfigValue>> {
-        if let ConfigValue::Map(m) = self {
-            Some(m)
-        } else {
-            None
+fn report(mut parsed: ParsedConfig, schema: &Schema, options: &Options, worst: &mut Option<Severity>) {
+    apply_schema_types(&mut parsed, schema);
+    if options.fill_defaults {
+        fill_defaults(&mut parsed, schema);
+    }
+    let mut validation = validate_config(&parsed, schema);
+    if let Some(severity) = options.unknown_keys {
+        validation.extend(check_unknown_keys(&parsed, schema, severity));
+        validation.sort_by(|a, b| a.key.cmp(&b.key));
+    }
+    for diagnostic in parsed.diagnostics.iter().chain(&validation) {
+        *worst = (*worst).max(Some(diagnostic.severity));
+        eprintln!("{}", diagnostic.render(&parsed.files));
+    }
+    println!("{}", serde_json::to_string_pretty(&format_as_json(&parsed.values)).unwrap());
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    match parse_command(&args[1..]) {
+        Ok(Command::Check(options)) => ExitCode::from(run(&options)),
+        Ok(Command::ExportJsonSchema(schema_path)) => ExitCode::from(export_json_schema(&schema_path)),
+        Ok(Command::Infer(paths)) => ExitCode::from(infer(&paths)),
+        Ok(Command::Doc(format, schema_path)) => ExitCode::from(doc(format, &schema_path)),
+        Err(e) => {
+            eprintln!("エラー: {}", e);
+            eprintln!("使用方法: {} [--fail-on=warning|error] [--on-conflict=error|last-wins|keep-first] [--on-duplicate=last-wins|first-wins|error] [--strict-syntax] [--detect-types] [--fill-defaults] [--unknown-keys=warning|error] <スキーマファイル> <設定ファイルまたはディレクトリ>...", args[0]);
+            eprintln!("        {} [オプション] --systemd [--root=<ルートディレクトリ>] <スキーマファイル>", args[0]);
+            eprintln!("        {} export-json-schema <スキーマファイル>", args[0]);
+            eprintln!("        {} infer <設定ファイルまたはディレクトリ>...", args[0]);
+            eprintln!("        {} doc [--format=markdown|html] <スキーマファイル>", args[0]);
+            ExitCode::from(EXIT_USAGE)
         }
     }
 }
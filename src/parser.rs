@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::error::{Error, Result};
+use crate::value::{assemble_lists, insert_config_value, parse_scalar, remove_config_value, ConfigValue};
+
+lazy_static! {
+    static ref CONFIG_REGEX: Regex = Regex::new(r"^\s*(-)?\s*([a-zA-Z0-9._/][a-zA-Z0-9._/-]*)\s*=\s*(.+?)\s*$").unwrap();
+    pub(crate) static ref COMMENT_REGEX: Regex = Regex::new(r"^\s*[#;]").unwrap();
+}
+
+/// `log = on` と `log.file = x` のように、スカラー値とセクションが衝突した場合の扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// 先の定義を残してエラーを報告する
+    Error,
+    /// 後の定義で置き換えて警告を報告する
+    LastWins,
+    /// 先の定義を残して警告を報告する
+    KeepFirst,
+}
+
+/// 同じファイル内で同じキーが複数回定義された場合の扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// 先の定義を残してエラーを報告する
+    Error,
+    /// 後の定義を採用して警告を報告する (sysctl と同じ挙動)
+    LastWins,
+    /// 先の定義を残して警告を報告する
+    FirstWins,
+}
+
+/// 設定ファイルの解析方法。
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub conflict_policy: ConflictPolicy,
+    pub duplicate_policy: DuplicatePolicy,
+    /// 解析できない行を警告ではなくエラーとして報告する
+    pub strict_syntax: bool,
+    /// 値が整数・浮動小数点数・時間・バイト数として解釈できればその型にする
+    pub detect_types: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            conflict_policy: ConflictPolicy::Error,
+            duplicate_policy: DuplicatePolicy::LastWins,
+            strict_syntax: false,
+            detect_types: false,
+        }
+    }
+}
+
+/// ソース上の範囲。列番号は1始まりの文字単位で、`end` は含まない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// [`ParsedConfig::files`] の添字
+    pub file: usize,
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub(crate) fn from_byte_range(file: usize, line: &str, line_number: usize, start: usize, end: usize) -> Self {
+        let start_column = line[..start].chars().count() + 1;
+        Span {
+            file,
+            line: line_number,
+            start: start_column,
+            end: start_column + line[start..end].chars().count(),
+        }
+    }
+}
+
+/// キーとその値の定義位置。途中のセクションは値を持たない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntrySpan {
+    pub key: Span,
+    pub value: Option<Span>,
+}
+
+/// 読み込んだファイルのパスと内容。
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// 1つ以上のファイルを順に読み込んだ結果。後から読み込んだファイルの値が優先される。
+#[derive(Debug, Default)]
+pub struct ParsedConfig {
+    pub files: Vec<SourceFile>,
+    pub values: HashMap<String, ConfigValue>,
+    /// キーパス (途中のセクションを含む) ごとの定義位置
+    pub spans: HashMap<String, EntrySpan>,
+    /// 先頭に `-` を付けて定義されたキー
+    pub ignore_errors: HashSet<String>,
+    /// 解析中に見つかった問題
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParsedConfig {
+    /// 値の位置を返す。セクションの場合はキーの位置を返す。
+    pub fn value_span(&self, key: &str) -> Option<Span> {
+        self.spans.get(key).map(|span| span.value.unwrap_or(span.key))
+    }
+
+    /// キーの定義位置を返す。分割した値の要素や存在しないキーなど、キー自体に位置がない場合は
+    /// 位置のある最も近い親のキーの定義位置を返す。
+    pub fn nearest_span(&self, key: &str) -> Option<EntrySpan> {
+        std::iter::successors(Some(key), |k| k.rsplit_once('.').map(|(parent, _)| parent)).find_map(|k| self.spans.get(k).copied())
+    }
+
+    /// 値として書かれた文字列をソースから取り出す。
+    pub fn raw_value(&self, key: &str) -> Option<&str> {
+        let span = self.spans.get(key)?.value?;
+        let line = self.files[span.file].text.lines().nth(span.line - 1)?;
+        let start = line.char_indices().nth(span.start - 1).map_or(line.len(), |(i, _)| i);
+        let end = line.char_indices().nth(span.end - 1).map_or(line.len(), |(i, _)| i);
+        Some(&line[start..end])
+    }
+
+    // 同じファイル内なら行番号のみ、別ファイルならパスを含めた位置の説明を返す
+    pub(crate) fn describe(&self, span: Span, current_file: usize) -> String {
+        if span.file == current_file {
+            format!("行 {}", span.line)
+        } else {
+            format!("{}:{}", self.files[span.file].path.display(), span.line)
+        }
+    }
+
+    // キーの定義位置があればそのファイルを、単一ファイルの場合はそのファイルを返す
+    pub(crate) fn file_of(&self, span: Option<Span>) -> Option<&Path> {
+        match (span, self.files.as_slice()) {
+            (Some(span), _) => Some(&self.files[span.file].path),
+            (None, [only]) => Some(&only.path),
+            _ => None,
+        }
+    }
+
+    /// 解析時にエラーが報告されていれば、最初のエラーを [`Error`] として返す。
+    pub fn into_result(self) -> Result<Self> {
+        match self.diagnostics.iter().find(|diagnostic| diagnostic.severity == Severity::Error) {
+            Some(diagnostic) => Err(diagnostic.clone().into_error()),
+            None => Ok(self),
+        }
+    }
+
+    /// 設定ファイルを追加で読み込む。
+    pub fn add_file(&mut self, file_path: &Path, options: &ParseOptions) -> Result<()> {
+        let source = read_file(file_path)?;
+        self.add_source(&source, file_path, options);
+        Ok(())
+    }
+
+    /// 設定を追加で読み込む。既存の値は上書きされ、別ファイルでの再定義は重複として報告しない。
+    pub fn add_source(&mut self, source: &str, file_path: &Path, options: &ParseOptions) {
+        let file = self.files.len();
+        self.files.push(SourceFile { path: file_path.to_path_buf(), text: source.to_string() });
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let trimmed_line = line.trim();
+            if COMMENT_REGEX.is_match(trimmed_line) || trimmed_line.is_empty() {
+                continue; // コメント行・空行をスキップ
+            }
+
+            // 空の区切りを含むキー (`/net/x` や `a..b`) は解析できない行として報告する
+            if let Some(captures) = CONFIG_REGEX.captures(line).filter(|captures| !has_empty_segment(&normalize_key(&captures[2]))) {
+                let key_match = captures.get(2).unwrap();
+                let value_match = captures.get(3).unwrap();
+                let key = normalize_key(key_match.as_str());
+                let raw_value = value_match.as_str();
+                let key_span = Span::from_byte_range(file, line, line_number, key_match.start(), key_match.end());
+                let value_span = Span::from_byte_range(file, line, line_number, value_match.start(), value_match.end());
+                let value = parse_scalar(raw_value, options.detect_types);
+
+                // 別ファイルでの再定義は sysctl.d と同様に上書きとして扱う
+                let previous = self.spans.get(&key).filter(|span| span.value.is_some()).copied();
+                if let Some(previous) = previous.filter(|span| span.key.file == file) {
+                    let severity = match options.duplicate_policy {
+                        DuplicatePolicy::Error => Severity::Error,
+                        DuplicatePolicy::LastWins | DuplicatePolicy::FirstWins => Severity::Warning,
+                    };
+                    self.diagnostics.push(
+                        Diagnostic::new(
+                            severity,
+                            "duplicate-key",
+                            &key,
+                            format!("キー '{}' は行 {} で既に定義されています", key, previous.key.line),
+                        )
+                        .with_file(file_path)
+                        .with_span(key_span)
+                        .with_related(previous.key, "先の定義"),
+                    );
+                    if options.duplicate_policy != DuplicatePolicy::LastWins {
+                        continue;
+                    }
+                }
+
+                if let Err(conflict_path) = insert_config_value(&mut self.values, &key, value.clone()) {
+                    let first = self.spans.get(&conflict_path).copied();
+                    let first_location = first.map(|span| self.describe(span.key, file)).unwrap_or_default();
+                    let severity = match options.conflict_policy {
+                        ConflictPolicy::Error => Severity::Error,
+                        ConflictPolicy::LastWins | ConflictPolicy::KeepFirst => Severity::Warning,
+                    };
+                    let message = if conflict_path == key {
+                        format!("キー '{}' の値は{} で定義されたセクション '{}' と衝突します", key, first_location, conflict_path)
+                    } else {
+                        format!("キー '{}' は{} で値が定義された '{}' と衝突します", key, first_location, conflict_path)
+                    };
+                    let mut diagnostic = Diagnostic::new(severity, "key-conflict", &key, message)
+                        .with_file(file_path)
+                        .with_span(key_span);
+                    if let Some(first) = first {
+                        diagnostic = diagnostic.with_related(first.value.unwrap_or(first.key), "先の定義");
+                    }
+                    self.diagnostics.push(diagnostic);
+
+                    if options.conflict_policy != ConflictPolicy::LastWins {
+                        continue;
+                    }
+                    remove_config_value(&mut self.values, &conflict_path);
+                    let nested_prefix = format!("{}.", conflict_path);
+                    self.spans.retain(|path, _| path != &conflict_path && !path.starts_with(&nested_prefix));
+                    insert_config_value(&mut self.values, &key, value)
+                        .expect("衝突したキーは削除済み");
+                }
+
+                // 途中のセクションは最初に現れた位置を、キー自体は最後に設定された位置を記録する
+                let keys: Vec<&str> = key.split('.').collect();
+                for i in 0..keys.len() - 1 {
+                    let section = keys[..=i].join(".");
+                    let section_span = Span { end: key_span.start + section.chars().count(), ..key_span };
+                    self.spans.entry(section).or_insert(EntrySpan { key: section_span, value: None });
+                }
+                // 先頭の '-' は sysctl と同様に「設定に失敗しても無視する」ことを表す
+                if captures.get(1).is_some() {
+                    self.ignore_errors.insert(key.clone());
+                } else {
+                    self.ignore_errors.remove(&key);
+                }
+                self.spans.insert(key, EntrySpan { key: key_span, value: Some(value_span) });
+            } else {
+                let severity = if options.strict_syntax { Severity::Error } else { Severity::Warning };
+                let (hint, span) = syntax_hint(file, line, line_number);
+                self.diagnostics.push(
+                    Diagnostic::new(severity, "syntax-error", "", format!("行を解析できません: '{}'", trimmed_line))
+                        .with_file(file_path)
+                        .with_span(span)
+                        .with_hint(hint),
+                );
+            }
+        }
+
+        // `servers.0`, `servers.1` のような連番のキーは配列にまとめる
+        assemble_lists(&mut self.values);
+    }
+}
+
+/// 設定ファイルを読み込む。
+pub fn parse_config_file(file_path: &Path, options: &ParseOptions) -> Result<ParsedConfig> {
+    let source = read_file(file_path)?;
+    Ok(parse_config_str(&source, file_path, options))
+}
+
+/// 設定を任意の [`Read`] から読み込む。`file_path` は診断に表示する名前として使う。
+pub fn parse_config_reader<R: Read>(reader: R, file_path: &Path, options: &ParseOptions) -> Result<ParsedConfig> {
+    let source = read_to_string(reader, Some(file_path))?;
+    Ok(parse_config_str(&source, file_path, options))
+}
+
+/// 設定を文字列から読み込む。`file_path` は診断に表示する名前として使う。
+pub fn parse_config_str(source: &str, file_path: &Path, options: &ParseOptions) -> ParsedConfig {
+    let mut parsed = ParsedConfig::default();
+    parsed.add_source(source, file_path, options);
+    parsed
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<String> {
+    let file = fs::File::open(path).map_err(|e| Error::io(Some(path), e))?;
+    read_to_string(file, Some(path))
+}
+
+pub(crate) fn read_to_string<R: Read>(mut reader: R, path: Option<&Path>) -> Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|e| Error::io(path, e))?;
+    String::from_utf8(bytes).map_err(|e| {
+        // 不正なバイトの直前までは有効なUTF-8なので、そこから行と列を求める
+        let source = e.utf8_error();
+        let valid = std::str::from_utf8(&e.as_bytes()[..source.valid_up_to()]).unwrap_or_default();
+        let line_start = valid.rfind('\n').map_or(0, |i| i + 1);
+        let start = valid[line_start..].chars().count() + 1;
+        Error::Encoding {
+            path: path.map(Path::to_path_buf),
+            span: Some(Span { file: 0, line: valid.matches('\n').count() + 1, start, end: start + 1 }),
+            source,
+        }
+    })
+}
+
+// sysctl.d(5) と同様に、最初の区切り文字が '/' の場合は '/' と '.' を入れ替えて解釈する
+// (例: net/ipv4/conf/eth0.100/forwarding -> net.ipv4.conf.eth0/100.forwarding)
+pub(crate) fn normalize_key(key: &str) -> String {
+    match key.find(['.', '/']) {
+        Some(i) if key.as_bytes()[i] == b'/' => key
+            .chars()
+            .map(|c| match c {
+                '/' => '.',
+                '.' => '/',
+                c => c,
+            })
+            .collect(),
+        _ => key.to_string(),
+    }
+}
+
+// 解析できなかった行について、ありがちな誤りのヒントと指摘箇所を返す
+fn syntax_hint(file: usize, line: &str, line_number: usize) -> (String, Span) {
+    let content_start = line.len() - line.trim_start().len();
+    let content_end = line.trim_end().len();
+    let whole = Span::from_byte_range(file, line, line_number, content_start, content_end);
+    // 先頭の '-' (エラー無視の指定) はキーに含めない
+    let key_start = match line[content_start..].strip_prefix('-') {
+        Some(rest) => line.len() - rest.trim_start().len(),
+        None => content_start,
+    };
+
+    let Some(eq) = line.find('=') else {
+        return ("'=' がありません。'キー = 値' の形式で記述してください".to_string(), whole);
+    };
+    let key = line[key_start.min(eq)..eq].trim();
+    if key.is_empty() {
+        return ("キーが空です".to_string(), Span::from_byte_range(file, line, line_number, eq, eq + 1));
+    }
+    if line[eq + 1..].trim().is_empty() {
+        return ("値が空です".to_string(), Span::from_byte_range(file, line, line_number, eq, eq + 1));
+    }
+    if let Some((offset, c)) = key.char_indices().find(|(_, c)| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'))) {
+        let start = key_start + offset;
+        return (
+            format!("キーに使用できない文字 '{}' が含まれています (使用可能: 英数字 . _ - /)", c),
+            Span::from_byte_range(file, line, line_number, start, start + c.len_utf8()),
+        );
+    }
+    if has_empty_segment(&normalize_key(key)) {
+        return (
+            "キーの先頭・末尾に区切り文字 ('.' または '/') があるか、区切り文字が連続しています".to_string(),
+            Span::from_byte_range(file, line, line_number, key_start, key_start + key.len()),
+        );
+    }
+    ("構文が不正です".to_string(), whole)
+}
+
+fn has_empty_segment(key: &str) -> bool {
+    key.split('.').any(str::is_empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_key_swaps_separators_when_slash_comes_first() {
+        assert_eq!(normalize_key("net.ipv4.ip_forward"), "net.ipv4.ip_forward");
+        assert_eq!(normalize_key("net/ipv4/ip_forward"), "net.ipv4.ip_forward");
+        assert_eq!(normalize_key("net/ipv4/conf/eth0.100/forwarding"), "net.ipv4.conf.eth0/100.forwarding");
+        assert_eq!(normalize_key("net.ipv4.conf.eth0/100.forwarding"), "net.ipv4.conf.eth0/100.forwarding");
+        assert_eq!(normalize_key("/net/x"), ".net.x");
+    }
+
+    fn hint(line: &str) -> (String, usize, usize) {
+        let (hint, span) = syntax_hint(0, line, 1);
+        (hint, span.start, span.end)
+    }
+
+    #[test]
+    fn syntax_hint_points_at_the_problem() {
+        assert_eq!(hint("net.ipv4 1"), ("'=' がありません。'キー = 値' の形式で記述してください".to_string(), 1, 11));
+        assert_eq!(hint("  = 1"), ("キーが空です".to_string(), 3, 4));
+        assert_eq!(hint("key ="), ("値が空です".to_string(), 5, 6));
+        assert_eq!(
+            hint("- ke y = 1"),
+            ("キーに使用できない文字 ' ' が含まれています (使用可能: 英数字 . _ - /)".to_string(), 5, 6)
+        );
+    }
+
+    #[test]
+    fn keys_with_empty_segments_are_syntax_errors() {
+        let parsed = parse_config_str("/net/x = 3\na..b = 1\nok = 1\n", Path::new("test.conf"), &ParseOptions::default());
+
+        assert_eq!(parsed.values.keys().collect::<Vec<_>>(), ["ok"]);
+        let codes: Vec<_> = parsed.diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.span.unwrap().line)).collect();
+        assert_eq!(codes, [("syntax-error", 1), ("syntax-error", 2)]);
+        assert_eq!(
+            hint("a..b = 1"),
+            ("キーの先頭・末尾に区切り文字 ('.' または '/') があるか、区切り文字が連続しています".to_string(), 1, 5)
+        );
+    }
+
+    #[test]
+    fn reader_and_str_parse_the_same_values() {
+        let source = "log.file = /var/log/app.log\ndebug = true\n";
+        let from_reader = parse_config_reader(source.as_bytes(), Path::new("test.conf"), &ParseOptions::default()).unwrap();
+        let from_str = parse_config_str(source, Path::new("test.conf"), &ParseOptions::default());
+
+        assert_eq!(from_reader.values, from_str.values);
+        assert_eq!(from_reader.files[0].path, Path::new("test.conf"));
+    }
+
+    #[test]
+    fn into_result_returns_the_first_error() {
+        let options = ParseOptions { strict_syntax: true, ..ParseOptions::default() };
+        let parsed = parse_config_str("ok = 1
+broken
+", Path::new("test.conf"), &options);
+
+        match parsed.into_result() {
+            Err(Error::Syntax { path, span, .. }) => {
+                assert_eq!(path.as_deref(), Some(Path::new("test.conf")));
+                assert_eq!(span.map(|span| span.line), Some(2));
+            }
+            other => panic!("Syntax を期待しましたが {:?} でした", other.map(|_| ())),
+        }
+        // 警告のみの場合は解析結果をそのまま返す
+        assert!(parse_config_str("broken
+", Path::new("test.conf"), &ParseOptions::default()).into_result().is_ok());
+        assert!(matches!(parse_config_reader(&[0xff_u8][..], Path::new("test.conf"), &ParseOptions::default()), Err(Error::Encoding { .. })));
+    }
+
+    fn parse_with(source: &str, options: ParseOptions) -> ParsedConfig {
+        parse_config_str(source, Path::new("test.conf"), &options)
+    }
+
+    // 診断の識別子・重大度・行番号
+    fn reported(parsed: &ParsedConfig) -> Vec<(&'static str, Severity, usize)> {
+        parsed.diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.severity, diagnostic.span.unwrap().line)).collect()
+    }
+
+    fn string(s: &str) -> ConfigValue {
+        ConfigValue::String(s.to_string())
+    }
+
+    #[test]
+    fn duplicate_keys_follow_the_policy() {
+        let source = "a = 1\nb = x\na = 2\n";
+        let cases = [
+            (DuplicatePolicy::LastWins, "2", Severity::Warning),
+            (DuplicatePolicy::FirstWins, "1", Severity::Warning),
+            (DuplicatePolicy::Error, "1", Severity::Error),
+        ];
+        for (duplicate_policy, expected, severity) in cases {
+            let parsed = parse_with(source, ParseOptions { duplicate_policy, ..ParseOptions::default() });
+
+            assert_eq!(parsed.values["a"], string(expected), "{:?}", duplicate_policy);
+            assert_eq!(reported(&parsed), [("duplicate-key", severity, 3)], "{:?}", duplicate_policy);
+            assert_eq!(parsed.diagnostics[0].related[0].0.line, 1);
+            assert_eq!(parsed.spans["a"].value.unwrap().line, if expected == "2" { 3 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn redefinitions_in_other_files_are_not_duplicates() {
+        let options = ParseOptions { duplicate_policy: DuplicatePolicy::Error, ..ParseOptions::default() };
+        let mut parsed = ParsedConfig::default();
+        parsed.add_source("a = 1\n", Path::new("10-base.conf"), &options);
+        parsed.add_source("a = 2\n", Path::new("20-local.conf"), &options);
+
+        assert!(parsed.diagnostics.is_empty());
+        assert_eq!(parsed.values["a"], string("2"));
+        assert_eq!(parsed.spans["a"].key.file, 1);
+    }
+
+    #[test]
+    fn scalar_and_section_conflicts_follow_the_policy() {
+        let section = || ConfigValue::Map(HashMap::from([("file".to_string(), string("x"))]));
+        // (設定, 方針, 残る log の値, 診断の重大度)
+        let cases = [
+            ("log = on\nlog.file = x\n", ConflictPolicy::Error, string("on"), Severity::Error),
+            ("log = on\nlog.file = x\n", ConflictPolicy::LastWins, section(), Severity::Warning),
+            ("log = on\nlog.file = x\n", ConflictPolicy::KeepFirst, string("on"), Severity::Warning),
+            ("log.file = x\nlog = on\n", ConflictPolicy::Error, section(), Severity::Error),
+            ("log.file = x\nlog = on\n", ConflictPolicy::LastWins, string("on"), Severity::Warning),
+            ("log.file = x\nlog = on\n", ConflictPolicy::KeepFirst, section(), Severity::Warning),
+        ];
+        for (source, conflict_policy, expected, severity) in cases {
+            let parsed = parse_with(&format!("{}other = 1\n", source), ParseOptions { conflict_policy, ..ParseOptions::default() });
+
+            assert_eq!(parsed.values["log"], expected, "{:?} {:?}", source, conflict_policy);
+            assert_eq!(reported(&parsed), [("key-conflict", severity, 2)], "{:?} {:?}", source, conflict_policy);
+            assert_eq!(parsed.diagnostics[0].related[0].0.line, 1);
+            // 衝突の後の行も読み込みを続ける
+            assert_eq!(parsed.values["other"], string("1"));
+            // 置き換えた側の定義位置は残さない
+            assert_eq!(parsed.spans.contains_key("log.file"), matches!(parsed.values["log"], ConfigValue::Map(_)));
+        }
+    }
+}
@@ -0,0 +1,1148 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::error::{Error, Result};
+use crate::json_schema::{looks_like_json, parse_json_schema_str};
+use crate::parser::{read_file, read_to_string, ParsedConfig, Span, COMMENT_REGEX};
+use crate::value::{assemble_lists, format_duration, insert_config_value, lookup_path, lookup_path_mut, parse_duration, parse_float, parse_int, parse_size, ConfigValue, LookupError};
+
+lazy_static! {
+    static ref SCHEMA_REGEX: Regex = Regex::new(r"^\s*([a-zA-Z0-9._*-]+)\s*->\s*(.+?)\s*$").unwrap();
+    static ref LIST_SEPARATOR_REGEX: Regex = Regex::new(r"[\s,]+").unwrap();
+    static ref INCLUDE_REGEX: Regex = Regex::new(r"^\s*include\s+(.+?)(?:\s+at\s+([a-zA-Z0-9_-]+(?:\.[a-zA-Z0-9_-]+)*))?\s*$").unwrap();
+}
+
+const SCALAR_TYPES: [&str; 6] = ["string", "bool", "int", "float", "duration", "size"];
+const NUMERIC_TYPES: [&str; 4] = ["int", "float", "duration", "size"];
+
+/// キーパスからその型と制約への対応。
+///
+/// キーパスの区切りには、任意の1区切りに一致する `*` と、0個以上の区切りに一致する `**` を使える
+/// (`db.*.host`, `net.**.enabled`)。
+pub type Schema = HashMap<String, FieldSchema>;
+
+/// スキーマで定義した1つのキーの型と制約。
+#[derive(Debug, Clone)]
+pub struct FieldSchema {
+    /// `string`, `bool`, `map`, `int`, `float`, `duration`, `size`, `list` のいずれか、
+    /// または要素の型を指定した `list<int>`, `tuple<int,int>` など。
+    /// 型を省略して `enum(...)` や `regex(...)` だけを指定した場合は `string` になる
+    pub value_type: String,
+    /// 値 (`list` と `tuple` の場合は各要素) が満たすべき制約
+    pub constraints: Vec<Constraint>,
+    pub presence: Presence,
+    /// `= 値` で指定された既定値。既定値を持つキーは省略可能として扱う
+    pub default: Option<ConfigValue>,
+    /// `##` の注釈で記述したキーの説明
+    pub description: Option<String>,
+    /// `## example: 値` の注釈で記述した値の例
+    pub example: Option<String>,
+}
+
+/// キーが存在しない場合の扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    /// 指定なし。存在しない場合は警告を報告する
+    Expected,
+    /// `required`: 存在しない場合はエラーを報告する
+    Required,
+    /// `optional`: 存在しなくても報告しない
+    Optional,
+}
+
+impl FieldSchema {
+    /// 存在しなくても報告しないキーかどうか。
+    pub fn is_optional(&self) -> bool {
+        self.presence == Presence::Optional || self.default.is_some()
+    }
+
+    fn missing_severity(&self) -> Severity {
+        if self.presence == Presence::Required { Severity::Error } else { Severity::Warning }
+    }
+
+    fn key_label(&self) -> &'static str {
+        if self.presence == Presence::Required { "必須のキー" } else { "キー" }
+    }
+
+    // スキーマに記述する形式の制約。時間とバイト数の範囲は読み戻せるように単位を付ける
+    pub(crate) fn constraint_texts(&self) -> Vec<String> {
+        let element_type = element_type(&self.value_type, 0).unwrap_or(&self.value_type);
+        let unit = match element_type {
+            "duration" => "s",
+            "size" => "B",
+            _ => "",
+        };
+        let bound = |value: &Option<f64>| value.map(|value| format!("{}{}", value, unit)).unwrap_or_default();
+        self.constraints
+            .iter()
+            .map(|constraint| match constraint {
+                Constraint::Range { min, max } => format!("range({}..{})", bound(min), bound(max)),
+                constraint => constraint.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for FieldSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value_type)?;
+        for constraint in self.constraint_texts() {
+            write!(f, " {}", constraint)?;
+        }
+        match self.presence {
+            Presence::Expected => (),
+            Presence::Required => write!(f, " required")?,
+            Presence::Optional => write!(f, " optional")?,
+        }
+        if let Some(default) = &self.default {
+            write!(f, " = {}", raw_text(default))?;
+        }
+        Ok(())
+    }
+}
+
+// 値をスキーマの既定値として読み戻せる文字列にする
+pub(crate) fn raw_text(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::String(s) => s.clone(),
+        ConfigValue::Bool(b) => b.to_string(),
+        ConfigValue::Int(n) => n.to_string(),
+        ConfigValue::Float(x) => x.to_string(),
+        ConfigValue::Duration(d) => format_duration(*d),
+        ConfigValue::Bytes(b) => format!("{}B", b),
+        ConfigValue::List(items) => items.iter().map(raw_text).collect::<Vec<_>>().join(", "),
+        ConfigValue::Map(_) => String::new(),
+    }
+}
+
+/// スキーマで型に付け加える値の制約。
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// `enum(a|b|c)`: 列挙した値のいずれかである
+    Enum(Vec<String>),
+    /// `regex(/.../)`: 正規表現に一致する
+    Pattern(Regex),
+    /// `range(min..max)`: 数値が両端を含む範囲内にある。時間は秒数、バイト数はバイト単位で比較する
+    Range { min: Option<f64>, max: Option<f64> },
+    /// `len(min..max)`: 文字列の文字数が両端を含む範囲内にある
+    Length { min: Option<usize>, max: Option<usize> },
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn bound<T: fmt::Display>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+        match self {
+            Constraint::Enum(values) => write!(f, "enum({})", values.join("|")),
+            Constraint::Pattern(regex) => write!(f, "regex(/{}/)", regex.as_str()),
+            Constraint::Range { min, max } => write!(f, "range({}..{})", bound(min), bound(max)),
+            Constraint::Length { min, max } => write!(f, "len({}..{})", bound(min), bound(max)),
+        }
+    }
+}
+
+impl Constraint {
+    // 制約を満たさない場合は診断コードとメッセージ、ヒントを返す
+    fn check(&self, key: &str, value: &ConfigValue) -> Option<(&'static str, String, Option<String>)> {
+        match (self, value) {
+            // JSON Schema の数値や真偽値の enum は、値を既定値と同じ文字列にして比較する
+            (Constraint::Enum(values), value) if !value.is_section() && !values.contains(&raw_text(value)) => Some((
+                "invalid-enum",
+                format!("キー '{}' の値 '{}' は {} のいずれでもありません", key, raw_text(value), self),
+                Some(format!("{} のいずれかを指定してください", values.join(", "))),
+            )),
+            (Constraint::Pattern(regex), ConfigValue::String(s)) if !regex.is_match(s) => {
+                Some(("pattern-mismatch", format!("キー '{}' の値 '{}' がパターン /{}/ に一致しません", key, s, regex.as_str()), None))
+            }
+            (Constraint::Range { min, max }, value) => {
+                let n = numeric_value(value)?;
+                let in_range = min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max);
+                (!in_range).then(|| ("out-of-range", format!("キー '{}' の値 {} が範囲 {} の外にあります", key, n, self), None))
+            }
+            (Constraint::Length { min, max }, ConfigValue::String(s)) => {
+                let len = s.chars().count();
+                let in_range = min.is_none_or(|min| len >= min) && max.is_none_or(|max| len <= max);
+                (!in_range).then(|| ("invalid-length", format!("キー '{}' の値の長さ ({} 文字) が {} を満たしません", key, len, self), None))
+            }
+            _ => None,
+        }
+    }
+}
+
+// 範囲の比較に使う数値。時間は秒数にする
+fn numeric_value(value: &ConfigValue) -> Option<f64> {
+    match value {
+        ConfigValue::Int(n) => Some(*n as f64),
+        ConfigValue::Float(f) => Some(*f),
+        ConfigValue::Duration(d) => Some(d.as_secs_f64()),
+        ConfigValue::Bytes(b) => Some(*b as f64),
+        _ => None,
+    }
+}
+
+// 型の指定を1文字ずつ読み、文字の位置・文字・直前の文字を返す。`(` の直後から始まる `/.../` の中の文字は返さない。
+// `depth` は返した文字までの括弧 (`(` と `<`) の深さで、対応しない閉じ括弧があると負になる
+struct Scanner<'a> {
+    chars: std::str::CharIndices<'a>,
+    depth: i32,
+    in_regex: bool,
+    escaped: bool,
+    previous: char,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(spec: &'a str) -> Self {
+        Scanner { chars: spec.char_indices(), depth: 0, in_regex: false, escaped: false, previous: ' ' }
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = (usize, char, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (i, c) = self.chars.next()?;
+            let previous = std::mem::replace(&mut self.previous, c);
+            if self.in_regex {
+                match (self.escaped, c) {
+                    (false, '\\') => self.escaped = true,
+                    (false, '/') => self.in_regex = false,
+                    _ => self.escaped = false,
+                }
+                continue;
+            }
+            match c {
+                '/' if previous == '(' => self.in_regex = true,
+                '(' | '<' => self.depth += 1,
+                ')' | '>' => self.depth -= 1,
+                _ => (),
+            }
+            return Some((i, c, previous));
+        }
+    }
+}
+
+// 型の指定を空白で区切られた項と、`=` 以降の既定値に分ける。括弧の中と `/.../` の中では区切らない
+fn split_terms(spec: &str) -> std::result::Result<(Vec<&str>, Option<&str>), String> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    let mut scanner = Scanner::new(spec);
+
+    while let Some((i, c, _)) = scanner.next() {
+        match c {
+            ')' | '>' if scanner.depth < 0 => return Err(format!("'{}' の括弧が対応していません", spec)),
+            '=' if scanner.depth == 0 => {
+                terms.extend(start.map(|s| &spec[s..i]));
+                return Ok((terms, Some(spec[i + 1..].trim())));
+            }
+            c if c.is_whitespace() && scanner.depth == 0 => {
+                if let Some(s) = start.take() {
+                    terms.push(&spec[s..i]);
+                }
+            }
+            _ => (),
+        }
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(i);
+        }
+    }
+    if scanner.depth != 0 || scanner.in_regex {
+        return Err(format!("'{}' の括弧が閉じられていません", spec));
+    }
+    terms.extend(start.map(|s| &spec[s..]));
+    Ok((terms, None))
+}
+
+// 行末の `##` 以降を注釈として型の指定から分ける。括弧の中と `/.../` の中の `##` は注釈とみなさない
+fn split_annotation(spec: &str) -> (&str, Option<&str>) {
+    let mut scanner = Scanner::new(spec);
+    while let Some((i, c, previous)) = scanner.next() {
+        if c == '#' && scanner.depth <= 0 && previous.is_whitespace() && spec[i..].starts_with("##") {
+            return (spec[..i].trim_end(), Some(spec[i + 2..].trim()));
+        }
+    }
+    (spec, None)
+}
+
+// `##` の注釈の行から説明と値の例を取り出す。`example:` で始まる行を値の例とし、それ以外の行を説明としてつなげる
+fn apply_annotations<'a>(field: &mut FieldSchema, annotations: impl IntoIterator<Item = &'a str>) {
+    let mut description = Vec::new();
+    for annotation in annotations {
+        match annotation.strip_prefix("example:") {
+            Some(example) => field.example = Some(example.trim().to_string()),
+            None if annotation.is_empty() => (),
+            None => description.push(annotation),
+        }
+    }
+    if !description.is_empty() {
+        field.description = Some(description.join(" "));
+    }
+}
+
+// `min..max` を解釈する。どちらかを省略した場合は制限なしとする
+fn parse_bounds<T>(argument: &str, parse: impl Fn(&str) -> Option<T>) -> std::result::Result<(Option<T>, Option<T>), String> {
+    let (min, max) = argument.split_once("..").ok_or_else(|| format!("範囲 '{}' は 'min..max' の形式で記述してください", argument))?;
+    let bound = |text: &str| match text.trim() {
+        "" => Ok(None),
+        text => parse(text).map(Some).ok_or_else(|| format!("範囲の値 '{}' を解釈できません", text)),
+    };
+    Ok((bound(min)?, bound(max)?))
+}
+
+// `int range(0..100)`, `enum(a|b) required`, `bool = false` のような型の指定を解釈する
+fn parse_field(spec: &str) -> std::result::Result<FieldSchema, String> {
+    let mut value_type = None;
+    let mut constraint_terms = Vec::new();
+    let mut presence = Presence::Expected;
+    let (terms, default) = split_terms(spec)?;
+
+    for term in terms {
+        let (name, argument) = match term.split_once('(') {
+            Some((name, rest)) => (name, rest.strip_suffix(')').ok_or_else(|| format!("'{}' の括弧が対応していません", term))?),
+            None => (term, ""),
+        };
+        let term_type = match name {
+            "required" | "optional" if presence != Presence::Expected => {
+                return Err(format!("required と optional は1つだけ指定してください: '{}'", spec));
+            }
+            "required" => {
+                presence = Presence::Required;
+                continue;
+            }
+            "optional" => {
+                presence = Presence::Optional;
+                continue;
+            }
+            "enum" | "regex" | "range" | "len" => {
+                constraint_terms.push((name, argument));
+                continue;
+            }
+            _ => {
+                let term_type: String = term.chars().filter(|c| !c.is_whitespace()).collect();
+                let elements = split_compound(&term_type).map(|(_, elements)| elements);
+                match (term_type.as_str(), elements) {
+                    (_, Some(_)) if !matches!(term_type.split_once('<'), Some(("list" | "tuple", _))) => {
+                        return Err(format!("'{}' は使用できない型です (list<型> または tuple<型,...> を指定してください)", term_type));
+                    }
+                    (_, Some(elements)) if term_type.starts_with("list") && elements.len() != 1 => {
+                        return Err(format!("'{}' の要素の型は1つだけ指定してください", term_type));
+                    }
+                    (_, Some(elements)) => {
+                        if let Some(element) = elements.iter().find(|element| !SCALAR_TYPES.contains(element)) {
+                            return Err(format!("'{}' の要素の型 '{}' は使用できません (string, bool, int, float, duration, size のいずれか)", term_type, element));
+                        }
+                    }
+                    (t, None) if SCALAR_TYPES.contains(&t) || t == "map" || t == "list" => (),
+                    (t, None) => {
+                        return Err(format!("不明な型です: '{}' (string, bool, map, int, float, duration, size, list, list<型>, tuple<型,...>, enum(...), regex(/.../) のいずれか)", t));
+                    }
+                }
+                term_type
+            }
+        };
+        match &value_type {
+            Some(existing) if *existing != term_type => {
+                return Err(format!("型が複数指定されています: '{}' と '{}'", existing, term));
+            }
+            _ => value_type = Some(term_type),
+        }
+    }
+
+    // `enum` と `regex` だけを指定した場合は `string` とする
+    let value_type = match value_type {
+        Some(value_type) => value_type,
+        None if constraint_terms.iter().any(|(name, _)| matches!(*name, "enum" | "regex")) => "string".to_string(),
+        None => return Err(format!("'{}' に型が指定されていません", spec)),
+    };
+    // `list` と `tuple` では各要素に制約を適用する
+    let element_type = element_type(&value_type, 0).unwrap_or(&value_type).to_string();
+    let mut constraints = Vec::new();
+    for (name, argument) in constraint_terms {
+        let constraint = match name {
+            "enum" | "regex" if !accepts_strings(&value_type) => {
+                return Err(format!("{} は string, list<string>, string を含む tuple の型にのみ指定できます ('{}' に指定されています)", name, value_type));
+            }
+            "enum" => Constraint::Enum(argument.split('|').map(|value| value.trim().to_string()).collect()),
+            "regex" => {
+                let pattern = argument
+                    .trim()
+                    .strip_prefix('/')
+                    .and_then(|pattern| pattern.strip_suffix('/'))
+                    .ok_or_else(|| format!("正規表現 '{}' は /.../ で囲んでください", argument))?;
+                Constraint::Pattern(Regex::new(pattern).map_err(|e| format!("正規表現 '{}' が不正です: {}", pattern, e))?)
+            }
+            "range" if NUMERIC_TYPES.contains(&element_type.as_str()) => {
+                let (min, max) = parse_bounds(argument, |text| convert(text, &element_type).as_ref().and_then(numeric_value))?;
+                Constraint::Range { min, max }
+            }
+            "range" => return Err(format!("range は int, float, duration, size の型にのみ指定できます ('{}' に指定されています)", value_type)),
+            _ if accepts_strings(&value_type) => {
+                let (min, max) = parse_bounds(argument, |text| text.parse().ok())?;
+                Constraint::Length { min, max }
+            }
+            _ => return Err(format!("len は string, list<string>, string を含む tuple の型にのみ指定できます ('{}' に指定されています)", value_type)),
+        };
+        constraints.push(constraint);
+    }
+
+    let default = match default {
+        Some(_) if presence == Presence::Required => return Err("required のキーには既定値を指定できません".to_string()),
+        Some("") => return Err(format!("'{}' の既定値が指定されていません", spec)),
+        Some(raw) => {
+            let value = convert(raw, &value_type).ok_or_else(|| format!("既定値 '{}' は型 '{}' として解釈できません", raw, value_type))?;
+            if let Some(constraint) = violated_constraint(&constraints, &value) {
+                return Err(format!("既定値 '{}' が制約 {} を満たしません", raw, constraint));
+            }
+            Some(value)
+        }
+        None => None,
+    };
+
+    Ok(FieldSchema { value_type, constraints, presence, default, description: None, example: None })
+}
+
+// 値 (`list` と `tuple` の場合はいずれかの要素) が満たさない制約を返す。既定値の検証に使う
+pub(crate) fn violated_constraint<'a>(constraints: &'a [Constraint], value: &ConfigValue) -> Option<&'a Constraint> {
+    let elements = match value {
+        ConfigValue::List(items) => items.iter().collect(),
+        value => vec![value],
+    };
+    constraints.iter().find(|constraint| elements.iter().any(|element| constraint.check("", element).is_some()))
+}
+
+// 文字列の値 (`list` と `tuple` の場合は要素) を持つ型かどうか
+fn accepts_strings(value_type: &str) -> bool {
+    match split_compound(value_type) {
+        Some((_, elements)) => elements.contains(&"string"),
+        None => value_type == "string",
+    }
+}
+
+// `list<int>` や `tuple<int,string>` を `("list", ["int"])` のように分解する
+pub(crate) fn split_compound(value_type: &str) -> Option<(&str, Vec<&str>)> {
+    let (kind, rest) = value_type.split_once('<')?;
+    let elements = rest.strip_suffix('>')?.split(',').collect();
+    Some((kind, elements))
+}
+
+pub(crate) fn element_type(value_type: &str, index: usize) -> Option<&str> {
+    match split_compound(value_type)? {
+        ("list", elements) => elements.first().copied(),
+        (_, elements) => elements.get(index).copied(),
+    }
+}
+
+fn matches_type(expected_type: &str, value: &ConfigValue) -> bool {
+    match (split_compound(expected_type), value) {
+        (Some(("list", elements)), ConfigValue::List(items)) => items.iter().all(|item| matches_type(elements[0], item)),
+        (Some((_, elements)), ConfigValue::List(items)) => {
+            elements.len() == items.len() && elements.iter().zip(items).all(|(element, item)| matches_type(element, item))
+        }
+        (Some(_), _) => false,
+        // 連番のキーを持つセクションは配列にまとめられるが、`map` として宣言したスキーマも受け入れる
+        (None, ConfigValue::List(_)) if expected_type == "map" => true,
+        (None, value) => value.type_name() == expected_type,
+    }
+}
+
+// 元の文字列をスキーマの型として解釈する。`list` と `tuple` は空白またはカンマで分割する
+pub(crate) fn convert(raw: &str, value_type: &str) -> Option<ConfigValue> {
+    if let Some((kind, elements)) = split_compound(value_type) {
+        let parts: Vec<&str> = LIST_SEPARATOR_REGEX.split(raw.trim()).filter(|part| !part.is_empty()).collect();
+        if kind == "tuple" && parts.len() != elements.len() {
+            return None;
+        }
+        return parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| convert(part, element_type(value_type, i)?))
+            .collect::<Option<Vec<_>>>()
+            .map(ConfigValue::List);
+    }
+    match value_type {
+        "string" => Some(ConfigValue::String(raw.to_string())),
+        "bool" if raw.eq_ignore_ascii_case("true") || raw.eq_ignore_ascii_case("false") => {
+            Some(ConfigValue::Bool(raw.eq_ignore_ascii_case("true")))
+        }
+        "int" => parse_int(raw).map(ConfigValue::Int),
+        "float" => parse_float(raw).map(ConfigValue::Float),
+        "duration" => parse_duration(raw).map(ConfigValue::Duration),
+        "size" => parse_size(raw).map(ConfigValue::Bytes),
+        _ => None,
+    }
+}
+
+/// 設定がスキーマを満たしているか検証し、見つかった問題をキー順に返す。
+///
+/// スキーマのキーが存在しない場合は警告 (`required` の場合はエラー、`optional` または既定値がある場合は報告しない)、
+/// 型または制約を満たさない場合はエラーとなる。
+pub fn validate_config(parsed: &ParsedConfig, schema: &Schema) -> Vec<Diagnostic> {
+    let config = &parsed.values;
+    let mut diagnostics = Vec::new();
+
+    for (key, field) in resolve_fields(schema, config) {
+        let key = &key;
+        let expected_type = &field.value_type;
+        match lookup_path(config, key) {
+            Ok(value) if matches_type(expected_type, value) => check_constraints(parsed, key, field, value, &mut diagnostics),
+            Ok(value) => diagnostics.push(
+                Diagnostic::new(
+                    // '-' 付きで定義されたキーは sysctl と同様に失敗を許容し、警告に留める
+                    if parsed.ignore_errors.contains(key) { Severity::Warning } else { Severity::Error },
+                    "type-mismatch",
+                    key,
+                    format!("キー '{}' の値が期待される型 '{}' と一致しません", key, expected_type),
+                )
+                .with_types(expected_type, value.type_name())
+                .with_optional_span(parsed.value_span(key)),
+            ),
+            Err(LookupError::MissingSection(_) | LookupError::MissingLeaf(_)) if field.is_optional() => (),
+            Err(LookupError::MissingSection(path)) => diagnostics.push(Diagnostic::new(
+                field.missing_severity(),
+                "missing-key",
+                key,
+                format!("{} '{}' が存在しません (セクション '{}' がありません)", field.key_label(), key, path),
+            )),
+            Err(LookupError::NotAMap(path)) => diagnostics.push(
+                Diagnostic::new(
+                    Severity::Error,
+                    "not-a-map",
+                    key,
+                    format!("キー '{}' を解決できません ('{}' がMapではありません)", key, path),
+                )
+                .with_types("map", lookup_path(config, &path).map(ConfigValue::type_name).unwrap_or("unknown"))
+                .with_optional_span(parsed.value_span(&path)),
+            ),
+            Err(LookupError::MissingLeaf(_)) => diagnostics.push(Diagnostic::new(
+                field.missing_severity(),
+                "missing-key",
+                key,
+                format!("{} '{}' が存在しません", field.key_label(), key),
+            )),
+        }
+    }
+
+    sorted_with_files(parsed, diagnostics)
+}
+
+// 診断をキー順に並べ、定義位置のファイルを付ける
+fn sorted_with_files(parsed: &ParsedConfig, mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.sort_by(|a, b| a.key.cmp(&b.key));
+    diagnostics
+        .into_iter()
+        .map(|diagnostic| match parsed.file_of(diagnostic.span) {
+            Some(file) => diagnostic.with_file(file),
+            None => diagnostic,
+        })
+        .collect()
+}
+
+fn is_wildcard(segment: &str) -> bool {
+    segment == "*" || segment == "**"
+}
+
+fn matches_pattern(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&"**", rest)), _) => matches_pattern(rest, path) || (!path.is_empty() && matches_pattern(pattern, &path[1..])),
+        (Some((&segment, rest)), Some((&key, path_rest))) => (segment == "*" || segment == key) && matches_pattern(rest, path_rest),
+        _ => false,
+    }
+}
+
+// ワイルドカードの少ないパターンほど優先する。`**` は `*` よりも優先度が低い
+fn specificity(pattern: &str) -> (usize, usize) {
+    let segments = pattern.split('.');
+    (segments.clone().filter(|s| *s == "**").count(), segments.filter(|s| *s == "*").count())
+}
+
+fn collect_paths<'a>(prefix: &str, value: &'a ConfigValue, paths: &mut Vec<(String, &'a ConfigValue)>) {
+    let children: Vec<(String, &ConfigValue)> = match value {
+        ConfigValue::Map(m) => m.iter().map(|(key, child)| (key.clone(), child)).collect(),
+        ConfigValue::List(items) => items.iter().enumerate().map(|(i, item)| (i.to_string(), item)).collect(),
+        _ => return,
+    };
+    for (key, child) in children {
+        let path = format!("{}.{}", prefix, key);
+        collect_paths(&path, child, paths);
+        paths.push((path, child));
+    }
+}
+
+// スキーマのキーを設定中の具体的なキーに展開する。
+// ワイルドカードを含むキーは、一致するキーと、一致するセクションに存在しない最後の区切りのキーに展開する
+// (`**` を含む場合は存在しないキーを補わない)。同じキーに複数の宣言が一致した場合は最も具体的な宣言を使う
+fn resolve_fields<'a>(schema: &'a Schema, config: &HashMap<String, ConfigValue>) -> Vec<(String, &'a FieldSchema)> {
+    let mut paths = Vec::new();
+    for (key, value) in config {
+        collect_paths(key, value, &mut paths);
+        paths.push((key.clone(), value));
+    }
+
+    let mut resolved: HashMap<String, ((usize, usize), &str, &FieldSchema)> = HashMap::new();
+    for (pattern, field) in schema {
+        let segments: Vec<&str> = pattern.split('.').collect();
+        let mut keys = Vec::new();
+        if !segments.iter().any(|segment| is_wildcard(segment)) {
+            keys.push(pattern.clone());
+        } else {
+            let (last, parent) = segments.split_last().unwrap();
+            for (path, value) in &paths {
+                let path_segments: Vec<&str> = path.split('.').collect();
+                if matches_pattern(&segments, &path_segments) {
+                    keys.push(path.clone());
+                } else if !pattern.contains("**")
+                    && !is_wildcard(last)
+                    && matches!(value, ConfigValue::Map(_))
+                    && matches_pattern(parent, &path_segments)
+                {
+                    keys.push(format!("{}.{}", path, last));
+                }
+            }
+        }
+        for key in keys {
+            let candidate = (specificity(pattern), pattern.as_str(), field);
+            match resolved.get(&key) {
+                Some((rank, name, _)) if (*rank, *name) <= (candidate.0, candidate.1) => (),
+                _ => {
+                    resolved.insert(key, candidate);
+                }
+            }
+        }
+    }
+
+    let mut fields: Vec<(String, &FieldSchema)> = resolved.into_iter().map(|(key, (_, _, field))| (key, field)).collect();
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+    fields
+}
+
+/// スキーマで宣言されていないキーを `severity` の重大度で報告する。
+///
+/// スキーマで宣言されたキーの配下 (`map` や `list` の要素) は宣言済みとみなす。
+/// 宣言済みのキーと綴りが近い場合は、ヒントとして候補を示す。
+pub fn check_unknown_keys(parsed: &ParsedConfig, schema: &Schema, severity: Severity) -> Vec<Diagnostic> {
+    let mut unknown = Vec::new();
+    for (key, value) in &parsed.values {
+        collect_unknown_keys(schema, key.clone(), value, &mut unknown);
+    }
+
+    let diagnostics = unknown
+        .into_iter()
+        .map(|key| {
+            // 分割した値の要素など、キー自体に位置がない場合は最も近い親の位置を示す
+            let span = parsed.nearest_span(&key).map(|span| span.key);
+            let diagnostic = Diagnostic::new(severity, "unknown-key", &key, format!("キー '{}' はスキーマで宣言されていません", key))
+                .with_optional_span(span);
+            match suggest_key(schema, &key) {
+                Some(candidate) => diagnostic.with_hint(format!("もしかして '{}' ですか?", candidate)),
+                None => diagnostic,
+            }
+        })
+        .collect();
+    sorted_with_files(parsed, diagnostics)
+}
+
+fn collect_unknown_keys(schema: &Schema, path: String, value: &ConfigValue, unknown: &mut Vec<String>) {
+    let segments: Vec<&str> = path.split('.').collect();
+    if schema.keys().any(|pattern| matches_pattern(&pattern.split('.').collect::<Vec<_>>(), &segments)) {
+        return;
+    }
+    match value {
+        ConfigValue::Map(m) => {
+            for (key, child) in m {
+                collect_unknown_keys(schema, format!("{}.{}", path, key), child, unknown);
+            }
+        }
+        ConfigValue::List(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_unknown_keys(schema, format!("{}.{}", path, i), item, unknown);
+            }
+        }
+        _ => unknown.push(path),
+    }
+}
+
+// 編集距離がキーの長さの1/3以下で最も近い宣言済みのキーを返す。
+// ワイルドカードを含むキーは、未知のキーの区切りで置き換えてから比べる
+fn suggest_key(schema: &Schema, key: &str) -> Option<String> {
+    let limit = (key.chars().count() / 3).max(1);
+    let segments: Vec<&str> = key.split('.').collect();
+    schema
+        .keys()
+        .map(|pattern| expand_pattern(&pattern.split('.').collect::<Vec<_>>(), &segments))
+        .map(|candidate| (edit_distance(key, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// パターンの `*` を同じ位置にあるキーの区切りに、最初の `**` を区切りの数の差を埋めるキーの区切りに置き換える。
+// 残りの `**` は0個の区切りに一致するものとして取り除く
+fn expand_pattern(pattern: &[&str], key: &[&str]) -> String {
+    let mut spread = (key.len() + pattern.iter().filter(|s| **s == "**").count()).saturating_sub(pattern.len());
+    let mut expanded = Vec::new();
+    for segment in pattern {
+        match *segment {
+            "**" => {
+                expanded.extend(key.iter().skip(expanded.len()).take(spread));
+                spread = 0;
+            }
+            "*" => expanded.push(key.get(expanded.len()).copied().unwrap_or("*")),
+            segment => expanded.push(segment),
+        }
+    }
+    expanded.join(".")
+}
+
+// 隣り合う文字の入れ替えを1回の操作と数える編集距離 (制限付きダメラウ・レーベンシュタイン距離)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    rows[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            rows[i][j] = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                rows[i][j] = rows[i][j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+fn check_constraints(parsed: &ParsedConfig, key: &str, field: &FieldSchema, value: &ConfigValue, diagnostics: &mut Vec<Diagnostic>) {
+    let elements: Vec<(String, &ConfigValue)> = match value {
+        ConfigValue::List(items) => items.iter().enumerate().map(|(i, item)| (format!("{}.{}", key, i), item)).collect(),
+        value => vec![(key.to_string(), value)],
+    };
+    for (element_key, element) in elements {
+        for (code, message, hint) in field.constraints.iter().filter_map(|constraint| constraint.check(&element_key, element)) {
+            let severity = if parsed.ignore_errors.contains(key) { Severity::Warning } else { Severity::Error };
+            let mut diagnostic = Diagnostic::new(severity, code, &element_key, message)
+                .with_optional_span(parsed.value_span(key))
+                .with_optional_span(parsed.value_span(&element_key));
+            if let Some(hint) = hint {
+                diagnostic = diagnostic.with_hint(hint);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+}
+
+/// スキーマが数値・時間・バイト数・リストを要求するキーの値を、元の文字列からその型に変換する。
+///
+/// `list<T>` と `tuple<...>` を要求するキーは、値を空白またはカンマで分割して要素ごとに変換する。
+/// `string` を要求するキーで型が推定されていた場合は文字列に戻す。
+/// 変換できない値はそのまま残し、[`validate_config`] で型の不一致として報告される。
+pub fn apply_schema_types(parsed: &mut ParsedConfig, schema: &Schema) {
+    for (key, field) in resolve_fields(schema, &parsed.values) {
+        let key = &key;
+        let expected_type = &field.value_type;
+        let converted = match lookup_path(&parsed.values, key) {
+            // 連番のキーから組み立てた配列は要素ごとに変換する
+            Ok(ConfigValue::List(items)) => (0..items.len())
+                .map(|i| convert(parsed.raw_value(&format!("{}.{}", key, i))?, element_type(expected_type, i)?))
+                .collect::<Option<Vec<_>>>()
+                .map(ConfigValue::List),
+            Ok(ConfigValue::Bool(_)) if expected_type == "string" => None,
+            Ok(ConfigValue::Map(_)) | Err(_) => None,
+            Ok(_) => parsed.raw_value(key).and_then(|raw| convert(raw, expected_type)),
+        };
+        if let (Some(converted), Some(value)) = (converted, lookup_path_mut(&mut parsed.values, key)) {
+            *value = converted;
+        }
+    }
+}
+
+/// スキーマで既定値が指定されたキーのうち、設定に存在しないものに既定値を設定する。
+///
+/// セクションとスカラー値が衝突するために設定できないキーは無視する。
+pub fn fill_defaults(parsed: &mut ParsedConfig, schema: &Schema) {
+    for (key, field) in resolve_fields(schema, &parsed.values) {
+        if let (Some(default), Err(LookupError::MissingSection(_) | LookupError::MissingLeaf(_))) =
+            (&field.default, lookup_path(&parsed.values, &key))
+        {
+            let _ = insert_config_value(&mut parsed.values, &key, default.clone());
+        }
+    }
+    assemble_lists(&mut parsed.values);
+}
+
+/// スキーマファイルを読み込む。
+///
+/// 拡張子が `.json` のファイルや内容が `{` で始まるファイルは JSON Schema として読み込む。
+/// `include` の相対パスはスキーマファイルのあるディレクトリを基準に解決する。
+pub fn load_schema(file_path: &Path) -> Result<Schema> {
+    load_schema_file(file_path, &mut Vec::new())
+}
+
+// `stack` は `include` をたどっている途中のファイル。循環した `include` の検出に使う
+fn load_schema_file(file_path: &Path, stack: &mut Vec<PathBuf>) -> Result<Schema> {
+    let source = read_file(file_path)?;
+    let is_json = file_path.extension().is_some_and(|extension| extension == "json") || looks_like_json(&source);
+    let schema = if is_json {
+        parse_json_schema_str(&source)
+    } else {
+        stack.push(fs::canonicalize(file_path).unwrap_or_else(|_| file_path.to_path_buf()));
+        let schema = parse_native_schema(&source, file_path.parent(), stack);
+        stack.pop();
+        schema
+    };
+    // `include` したファイルのエラーにはそのファイルのパスが設定済み
+    schema.map_err(|e| if e.path().is_some() { e } else { e.with_path(file_path) })
+}
+
+/// スキーマを任意の [`Read`] から読み込む。内容が `{` で始まる場合は JSON Schema として読み込む。
+pub fn parse_schema_reader<R: Read>(reader: R) -> Result<Schema> {
+    let source = read_to_string(reader, None)?;
+    if looks_like_json(&source) { parse_json_schema_str(&source) } else { parse_schema_str(&source) }
+}
+
+/// `key -> type` 形式のスキーマを文字列から読み込む。型の後には `range(0..100)` のような制約を続けられる。
+///
+/// 行末の `## 説明` と、直前に続く `##` で始まる行はキーの注釈として扱う。
+/// 注釈のうち `example:` で始まるものは値の例、それ以外は説明になる。
+///
+/// `include other.schema` の行は他のスキーマファイルの宣言を取り込み、`include db.schema at database` の行は
+/// 取り込んだキーを `database.` の配下に配置する。相対パスはカレントディレクトリを基準に解決する。
+/// 同じキーを異なる型で宣言した場合はエラーとし、同じ型の場合は後の宣言を採用する。
+///
+/// 解釈できない行や循環した `include` があれば [`Error::SchemaSyntax`] を返す。
+pub fn parse_schema_str(source: &str) -> Result<Schema> {
+    parse_native_schema(source, None, &mut Vec::new())
+}
+
+fn parse_native_schema(source: &str, base_dir: Option<&Path>, stack: &mut Vec<PathBuf>) -> Result<Schema> {
+    let mut schema = HashMap::new();
+    // キーごとの宣言した場所。異なる型の宣言を報告するときに示す
+    let mut origins: HashMap<String, String> = HashMap::new();
+    let mut annotations = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let trimmed_line = line.trim();
+        if let Some(annotation) = trimmed_line.strip_prefix("##") {
+            annotations.push(annotation.trim());
+            continue;
+        }
+        if COMMENT_REGEX.is_match(trimmed_line) || trimmed_line.is_empty() {
+            annotations.clear();
+            continue; // コメント行・空行をスキップ
+        }
+
+        let content_start = line.len() - line.trim_start().len();
+        let span = Some(Span::from_byte_range(0, line, index + 1, content_start, line.trim_end().len()));
+        let syntax_error = |message: String| Error::SchemaSyntax { path: None, span, message };
+        if let Some(captures) = SCHEMA_REGEX.captures(trimmed_line) {
+            if let Some(segment) = captures[1].split('.').find(|segment| segment.contains('*') && !is_wildcard(segment)) {
+                return Err(syntax_error(format!(
+                    "キー '{}' の '{}' は使用できません (ワイルドカードは区切り全体を '*' または '**' にしてください)",
+                    &captures[1], segment
+                )));
+            }
+            let (spec, trailing) = split_annotation(&captures[2]);
+            let mut field = parse_field(spec).map_err(syntax_error)?;
+            apply_annotations(&mut field, annotations.drain(..).chain(trailing));
+            declare(&mut schema, &mut origins, captures[1].to_string(), field, &format!("{} 行目", index + 1)).map_err(syntax_error)?;
+        } else if let Some(captures) = INCLUDE_REGEX.captures(trimmed_line) {
+            annotations.clear();
+            let include_path = base_dir.map(|dir| dir.join(&captures[1])).unwrap_or_else(|| PathBuf::from(&captures[1]));
+            let canonical = fs::canonicalize(&include_path).unwrap_or_else(|_| include_path.clone());
+            if let Some(start) = stack.iter().position(|path| *path == canonical) {
+                let cycle: Vec<String> = stack[start..].iter().chain([&canonical]).map(|path| path.display().to_string()).collect();
+                return Err(syntax_error(format!("include が循環しています: {}", cycle.join(" -> "))));
+            }
+            let included = load_schema_file(&include_path, stack)?;
+            let origin = include_path.display().to_string();
+            let mut fields: Vec<(String, FieldSchema)> = included.into_iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, field) in fields {
+                let key = match captures.get(2) {
+                    Some(mount) => format!("{}.{}", mount.as_str(), key),
+                    None => key,
+                };
+                declare(&mut schema, &mut origins, key, field, &origin).map_err(syntax_error)?;
+            }
+        } else {
+            return Err(syntax_error(format!("スキーマの行を解析できません: '{}' ('キー -> 型' の形式で記述してください)", trimmed_line)));
+        }
+    }
+
+    Ok(schema)
+}
+
+// キーの宣言を追加する。宣言済みのキーの場合、型が同じなら後の宣言で置き換え、異なればエラーにする
+fn declare(schema: &mut Schema, origins: &mut HashMap<String, String>, key: String, field: FieldSchema, origin: &str) -> std::result::Result<(), String> {
+    if let Some(existing) = schema.get(&key).filter(|existing| existing.value_type != field.value_type) {
+        return Err(format!(
+            "キー '{}' が異なる型で宣言されています: '{}' ({}) と '{}' ({})",
+            key, existing.value_type, origins[&key], field.value_type, origin
+        ));
+    }
+    origins.insert(key.clone(), origin.to_string());
+    schema.insert(key, field);
+    Ok(())
+}
+
+/// スキーマを [`parse_schema_str`] で読み込める `key -> type` 形式の文字列にする。行はキーの順に並べ、説明と値の例は `##` の注釈にする。
+pub fn format_schema(schema: &Schema) -> String {
+    let mut fields: Vec<_> = schema.iter().collect();
+    fields.sort_by_key(|(key, _)| key.as_str());
+    let mut text = String::new();
+    for (key, field) in fields {
+        if let Some(example) = &field.example {
+            text.push_str(&format!("## example: {}\n", example));
+        }
+        match &field.description {
+            Some(description) => text.push_str(&format!("{} -> {} ## {}\n", key, field, description)),
+            None => text.push_str(&format!("{} -> {}\n", key, field)),
+        }
+    }
+    text
+}
+
+/// [`validate_config`] でエラーが報告されれば、最初のエラーを [`Error::Validation`] として返す。
+pub fn check_config(parsed: &ParsedConfig, schema: &Schema) -> Result<()> {
+    match validate_config(parsed, schema).into_iter().find(|diagnostic| diagnostic.severity == Severity::Error) {
+        Some(diagnostic) => Err(diagnostic.into_error()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(key: &str) -> Vec<&str> {
+        key.split('.').collect()
+    }
+
+    fn schema(source: &str) -> Schema {
+        parse_schema_str(source).unwrap()
+    }
+
+    fn config(source: &str) -> ParsedConfig {
+        crate::parser::parse_config_str(source, Path::new("test.conf"), &Default::default())
+    }
+
+    // テストごとに別のディレクトリにスキーマファイルを書き出す
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jic_test_02-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        dir
+    }
+
+    fn schema_error(result: Result<Schema>) -> String {
+        match result {
+            Err(Error::SchemaSyntax { message, .. }) => message,
+            other => panic!("SchemaSyntax を期待しましたが {:?} でした", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn matches_pattern_with_wildcards() {
+        assert!(matches_pattern(&segments("db.*.host"), &segments("db.primary.host")));
+        assert!(!matches_pattern(&segments("db.*.host"), &segments("db.host")));
+        assert!(!matches_pattern(&segments("db.*.host"), &segments("db.a.b.host")));
+        assert!(matches_pattern(&segments("net.**.enabled"), &segments("net.enabled")));
+        assert!(matches_pattern(&segments("net.**.enabled"), &segments("net.ipv4.conf.all.enabled")));
+        assert!(!matches_pattern(&segments("net.**.enabled"), &segments("net.ipv4.disabled")));
+        assert!(matches_pattern(&segments("**"), &segments("any.key")));
+    }
+
+    #[test]
+    fn resolve_fields_prefers_specific_declarations() {
+        let schema = schema("db.*.host -> string required\ndb.special.host -> string optional\nnet.**.enabled -> bool\n");
+        let parsed = config("db.primary.port = 1\ndb.special.host = s\nnet.ipv4.enabled = 1\n");
+        let mut resolved: Vec<(String, Presence)> =
+            resolve_fields(&schema, &parsed.values).into_iter().map(|(key, field)| (key, field.presence)).collect();
+        resolved.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // 一致するセクションに存在しない `host` も補い、`**` の宣言は存在するキーのみに展開する
+        assert_eq!(
+            resolved,
+            [
+                ("db.primary.host".to_string(), Presence::Required),
+                ("db.special.host".to_string(), Presence::Optional),
+                ("net.ipv4.enabled".to_string(), Presence::Expected),
+            ]
+        );
+    }
+
+    #[test]
+    fn indexed_sections_match_map() {
+        let schema = schema("servers -> map\nports -> list\n");
+        let parsed = config("servers.0 = a\nservers.1 = b\nports.0 = 80\n");
+
+        assert!(matches!(parsed.values["servers"], ConfigValue::List(_)));
+        assert!(validate_config(&parsed, &schema).is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_suggestions() {
+        let schema = schema("log -> map\ndb.*.host -> string\nnet.** -> bool\nendpoint -> string\nports -> list<int>\n");
+        let mut parsed = config("log.file.path = a\ndb.main.host = h\ndb.main.hots = x\nnet.ipv4.conf.all = 1\nendpiont = u\nports = 80 443\n");
+        apply_schema_types(&mut parsed, &schema);
+        let reported: Vec<(String, Option<String>, usize)> = check_unknown_keys(&parsed, &schema, Severity::Warning)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.key, diagnostic.hint, diagnostic.span.unwrap().line))
+            .collect();
+
+        // `map` の配下と、ワイルドカードに一致するキー、配列の要素は報告しない
+        assert_eq!(
+            reported,
+            [
+                ("db.main.hots".to_string(), Some("もしかして 'db.main.host' ですか?".to_string()), 3),
+                ("endpiont".to_string(), Some("もしかして 'endpoint' ですか?".to_string()), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_list_elements_point_at_the_list() {
+        let schema = schema("name -> string\n");
+        let mut parsed = config("name = a\nports = 80 443\n");
+        apply_schema_types(&mut parsed, &parse_schema_str("ports -> list<int>\n").unwrap());
+        let diagnostics = check_unknown_keys(&parsed, &schema, Severity::Error);
+
+        let reported: Vec<(&str, usize)> = diagnostics.iter().map(|diagnostic| (diagnostic.key.as_str(), diagnostic.span.unwrap().line)).collect();
+        assert_eq!(reported, [("ports.0", 2), ("ports.1", 2)]);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Error));
+    }
+
+    #[test]
+    fn edit_distance_counts_transpositions_once() {
+        assert_eq!(edit_distance("host", "host"), 0);
+        assert_eq!(edit_distance("host", "hots"), 1);
+        assert_eq!(edit_distance("host", "hosts"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn suggest_key_expands_wildcards_against_the_key() {
+        let schema = schema("db.*.host -> string\nnet.**.enabled -> bool\nendpoint -> string\n");
+
+        assert_eq!(suggest_key(&schema, "db.replica.hots"), Some("db.replica.host".to_string()));
+        assert_eq!(suggest_key(&schema, "net.ipv4.conf.enabeld"), Some("net.ipv4.conf.enabled".to_string()));
+        assert_eq!(suggest_key(&schema, "endpiont"), Some("endpoint".to_string()));
+        assert_eq!(suggest_key(&schema, "unrelated"), None);
+    }
+
+    #[test]
+    fn split_terms_keeps_brackets_and_regexes_together() {
+        assert_eq!(split_terms("int range(0 .. 100) required"), Ok((vec!["int", "range(0 .. 100)", "required"], None)));
+        assert_eq!(split_terms("tuple<int, int>"), Ok((vec!["tuple<int, int>"], None)));
+        assert_eq!(split_terms("regex(/a = b\\/ c/) = a = b/ c"), Ok((vec!["regex(/a = b\\/ c/)"], Some("a = b/ c"))));
+        assert_eq!(split_terms("enum(a|b)=a"), Ok((vec!["enum(a|b)"], Some("a"))));
+        assert!(split_terms("range(0..1").is_err());
+        assert!(split_terms("int)").is_err());
+    }
+
+    #[test]
+    fn parse_field_reads_type_constraints_and_presence() {
+        let field = parse_field("int range(0..100) required").unwrap();
+        assert_eq!(field.value_type, "int");
+        assert_eq!(field.presence, Presence::Required);
+        assert!(matches!(field.constraints[..], [Constraint::Range { min: Some(0.0), max: Some(100.0) }]));
+
+        let field = parse_field("enum(debug|info) = info").unwrap();
+        assert_eq!(field.value_type, "string");
+        assert_eq!(field.presence, Presence::Expected);
+        assert_eq!(field.default, Some(ConfigValue::String("info".to_string())));
+
+        assert_eq!(parse_field("list<string> enum(a|b)").unwrap().value_type, "list<string>");
+        assert_eq!(parse_field("tuple< int , int >").unwrap().value_type, "tuple<int,int>");
+    }
+
+    #[test]
+    fn parse_field_rejects_invalid_specs() {
+        assert!(parse_field("required").is_err());
+        assert!(parse_field("int string").is_err());
+        assert!(parse_field("int required optional").is_err());
+        assert!(parse_field("number").is_err());
+        assert!(parse_field("list<int,int>").is_err());
+        assert!(parse_field("int enum(a|b)").is_err());
+        assert!(parse_field("string range(0..1)").is_err());
+        assert!(parse_field("regex(abc)").is_err());
+    }
+
+    #[test]
+    fn split_annotation_ignores_hashes_in_brackets_and_regexes() {
+        assert_eq!(split_annotation("string ## 説明"), ("string", Some("説明")));
+        assert_eq!(split_annotation("regex(/^a ## b$/) ## 説明"), ("regex(/^a ## b$/)", Some("説明")));
+        assert_eq!(split_annotation("enum(a|## b)"), ("enum(a|## b)", None));
+        assert_eq!(split_annotation("string = a##b"), ("string = a##b", None));
+    }
+
+    #[test]
+    fn annotations_before_and_after_declarations() {
+        let schema = schema(
+            "## 接続先のURL。\n## example: https://api.example.com\n## 末尾のスラッシュは不要\nendpoint -> string ## 必須の設定\n\
+             timeout -> duration = 10s ## 応答を待つ時間\n\
+             ## コメントで区切られた注釈は使わない\n# 区切り\nport -> int\n",
+        );
+
+        assert_eq!(schema["endpoint"].description.as_deref(), Some("接続先のURL。 末尾のスラッシュは不要 必須の設定"));
+        assert_eq!(schema["endpoint"].example.as_deref(), Some("https://api.example.com"));
+        assert_eq!(schema["timeout"].description.as_deref(), Some("応答を待つ時間"));
+        assert_eq!(schema["timeout"].default, Some(ConfigValue::Duration(std::time::Duration::from_secs(10))));
+        assert_eq!(schema["port"].description, None);
+
+        // format_schema の出力を読み戻しても説明と値の例が変わらない
+        let reparsed = parse_schema_str(&format_schema(&schema)).unwrap();
+        assert_eq!(reparsed["endpoint"].description, schema["endpoint"].description);
+        assert_eq!(reparsed["endpoint"].example, schema["endpoint"].example);
+    }
+
+    #[test]
+    fn include_mounts_and_overrides_declarations() {
+        let dir = write_files(
+            "include",
+            &[
+                ("db.schema", "host -> string required\nport -> int = 5432\n"),
+                ("main.schema", "include db.schema at database\ndatabase.port -> int = 6432\n"),
+            ],
+        );
+        let schema = load_schema(&dir.join("main.schema")).unwrap();
+
+        assert_eq!(schema["database.host"].presence, Presence::Required);
+        assert_eq!(schema["database.port"].default, Some(ConfigValue::Int(6432)));
+        assert_eq!(schema.len(), 2);
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let dir = write_files("include-cycle", &[("a.schema", "include b.schema\n"), ("b.schema", "x -> int\ninclude a.schema\n")]);
+        let message = schema_error(load_schema(&dir.join("a.schema")));
+
+        assert!(message.starts_with("include が循環しています: "), "{}", message);
+        assert!(message.ends_with("a.schema"), "{}", message);
+    }
+
+    #[test]
+    fn declarations_with_different_types_are_an_error() {
+        let dir = write_files("include-conflict", &[("common.schema", "timeout -> duration\n")]);
+        let source = format!("include {}\ntimeout -> int\n", dir.join("common.schema").display());
+        let message = schema_error(parse_schema_str(&source));
+        assert!(message.starts_with("キー 'timeout' が異なる型で宣言されています: 'duration'"), "{}", message);
+
+        let message = schema_error(parse_schema_str("a -> int\na -> string\n"));
+        assert_eq!(message, "キー 'a' が異なる型で宣言されています: 'int' (1 行目) と 'string' (2 行目)");
+    }
+}
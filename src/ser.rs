@@ -0,0 +1,755 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::time::Duration;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::error::{Error, Result};
+use crate::value::{format_duration, ConfigValue};
+
+/// [`to_string_with_options`] の出力方法。
+#[derive(Debug, Clone, Default)]
+pub struct SerializeOptions {
+    /// 先頭に `# ` 付きで出力するコメント。複数行も可
+    pub header: Option<String>,
+    /// 最上位のセクションごとにまとめ、空行と `# [section]` コメントで区切る
+    pub group_sections: bool,
+}
+
+/// 値を sysctl.conf 形式の文字列に変換する。ネストした構造体はドット区切りのキーに展開する。
+///
+/// 出力は読み込み時に同じ値として解釈される。`true`/`false` の文字列や空の配列・Map・構造体など、
+/// 同じ値として読み込めない値は [`Error::Serialize`] を返す。
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    to_string_with_options(value, &SerializeOptions::default())
+}
+
+/// [`SerializeOptions`] を指定して値を sysctl.conf 形式の文字列に変換する。
+pub fn to_string_with_options<T: Serialize + ?Sized>(value: &T, options: &SerializeOptions) -> Result<String> {
+    let mut entries = Vec::new();
+    value.serialize(ValueSerializer { entries: &mut entries, key: String::new() })?;
+
+    if options.group_sections {
+        // 最初に現れた順を保ったまま、同じセクションのキーを隣接させる
+        let mut order: Vec<String> = Vec::new();
+        for (key, _) in &entries {
+            let section = section_of(key);
+            if !order.iter().any(|s| s == section) {
+                order.push(section.to_string());
+            }
+        }
+        entries.sort_by_key(|(key, _)| order.iter().position(|s| s == section_of(key)));
+    }
+
+    let mut out = String::new();
+    if let Some(header) = &options.header {
+        for line in header.lines() {
+            out.push_str(format!("# {}", line).trim_end());
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    let mut current_section = None;
+    for (key, value) in &entries {
+        let section = section_of(key);
+        if options.group_sections && current_section != Some(section) {
+            if current_section.is_some() {
+                out.push('\n');
+            }
+            if !section.is_empty() {
+                out.push_str(&format!("# [{}]\n", section));
+            }
+            current_section = Some(section);
+        }
+        out.push_str(&format!("{} = {}\n", key, value));
+    }
+    Ok(out)
+}
+
+/// 値を sysctl.conf 形式で書き出す。
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(mut writer: W, value: &T, options: &SerializeOptions) -> Result<()> {
+    let text = to_string_with_options(value, options)?;
+    writer.write_all(text.as_bytes()).map_err(|e| Error::io(None, e))
+}
+
+// 最上位のキーは空のセクションに属するものとする
+fn section_of(key: &str) -> &str {
+    key.split_once('.').map_or("", |(section, _)| section)
+}
+
+// 読み込んだ設定を書き戻せるように、時間とバイト数は読み込み時に解釈される `1500ms` や `64B` の形式にする。
+// Map はキーの順に出力する
+impl Serialize for ConfigValue {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            ConfigValue::String(s) => serializer.serialize_str(s),
+            ConfigValue::Bool(b) => serializer.serialize_bool(*b),
+            ConfigValue::Int(n) => serializer.serialize_i64(*n),
+            ConfigValue::Float(f) => serializer.serialize_f64(*f),
+            ConfigValue::Duration(d) => serializer.serialize_str(&format_duration(*d)),
+            ConfigValue::Bytes(b) => serializer.serialize_str(&format!("{}B", b)),
+            ConfigValue::List(items) => serializer.collect_seq(items),
+            ConfigValue::Map(m) => serializer.collect_map(m.iter().collect::<BTreeMap<_, _>>()),
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Serialize { key: String::new(), message: msg.to_string() }
+    }
+}
+
+fn serialize_error(key: &str, message: impl Into<String>) -> Error {
+    Error::Serialize { key: key.to_string(), message: message.into() }
+}
+
+// 読み込み時にキーの1要素として解釈される文字だけを許可する
+fn check_segment(parent: &str, segment: &str) -> Result<()> {
+    let valid = !segment.is_empty()
+        && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+        && !(parent.is_empty() && segment.starts_with('-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(serialize_error(parent, format!("キー '{}' は出力できません (使用可能: 英数字 _ -)", segment)))
+    }
+}
+
+// 要素やフィールドから出力されるキーがない配列・Map・構造体は、読み込み時に存在しないキーになり往復できない
+fn check_written(entries: &[(String, String)], start: usize, key: &str) -> Result<()> {
+    if key.is_empty() || entries.len() > start {
+        Ok(())
+    } else {
+        Err(serialize_error(
+            key,
+            "空の配列・Map・構造体は出力できません (#[serde(default, skip_serializing_if = \"...\")] で省略してください)",
+        ))
+    }
+}
+
+fn join_key(parent: &str, segment: &str) -> String {
+    if parent.is_empty() { segment.to_string() } else { format!("{}.{}", parent, segment) }
+}
+
+struct ValueSerializer<'a> {
+    entries: &'a mut Vec<(String, String)>,
+    key: String,
+}
+
+impl ValueSerializer<'_> {
+    fn push(self, value: String) -> Result<()> {
+        if self.key.is_empty() {
+            return Err(serialize_error("", "最上位の値は構造体またはMapである必要があります"));
+        }
+        // 読み込み時に前後の空白は取り除かれ、空の値や改行は解析できないため、そのままでは往復できない
+        if value.is_empty() || value.trim() != value || value.contains(['\n', '\r']) {
+            return Err(serialize_error(&self.key, format!("値 {:?} は往復できる形式で出力できません", value)));
+        }
+        self.entries.push((self.key, value));
+        Ok(())
+    }
+
+    fn child(&mut self, segment: &str) -> Result<ValueSerializer<'_>> {
+        check_segment(&self.key, segment)?;
+        Ok(ValueSerializer { entries: self.entries, key: join_key(&self.key, segment) })
+    }
+}
+
+impl<'a> ser::Serializer for ValueSerializer<'a> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    // `1.0` を `1` と出力すると `--detect-types` で整数として読み込まれるため、小数点を残す
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.push(format!("{:?}", v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.push(format!("{:?}", v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.push(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        // `true`/`false` は読み込み時に真偽値として解釈されるため、文字列としては往復できない
+        if v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false") {
+            return Err(serialize_error(&self.key, format!("文字列 {:?} は真偽値として読み込まれるため出力できません", v)));
+        }
+        self.push(v.to_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(serialize_error(&self.key, "バイト列は出力できません"))
+    }
+
+    // 値のないキーは出力しない。読み込み時は存在しないキーとして扱われる
+    fn serialize_none(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<()> {
+        self.push(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        mut self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self.child(variant)?)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqSerializer<'a>> {
+        let start = self.entries.len();
+        Ok(SeqSerializer { entries: self.entries, key: self.key, index: 0, start })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SeqSerializer<'a>> {
+        check_segment(&self.key, variant)?;
+        let start = self.entries.len();
+        Ok(SeqSerializer { key: join_key(&self.key, variant), entries: self.entries, index: 0, start })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer<'a>> {
+        let start = self.entries.len();
+        Ok(MapSerializer { entries: self.entries, key: self.key, next_key: None, start, duration: false })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<MapSerializer<'a>> {
+        let mut map = self.serialize_map(Some(len))?;
+        map.duration = name == "Duration" && len == 2;
+        Ok(map)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer<'a>> {
+        check_segment(&self.key, variant)?;
+        let start = self.entries.len();
+        Ok(MapSerializer { key: join_key(&self.key, variant), entries: self.entries, next_key: None, start, duration: false })
+    }
+}
+
+// 配列は `servers.0 = a` のように添字をキーにして展開する
+struct SeqSerializer<'a> {
+    entries: &'a mut Vec<(String, String)>,
+    key: String,
+    index: usize,
+    // 配列を出力し始めたときの `entries` の長さ
+    start: usize,
+}
+
+impl SeqSerializer<'_> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        if self.key.is_empty() {
+            return Err(serialize_error("", "最上位の値は構造体またはMapである必要があります"));
+        }
+        let key = join_key(&self.key, &self.index.to_string());
+        self.index += 1;
+        value.serialize(ValueSerializer { entries: self.entries, key })
+    }
+
+    fn finish(self) -> Result<()> {
+        check_written(self.entries, self.start, &self.key)
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+struct MapSerializer<'a> {
+    entries: &'a mut Vec<(String, String)>,
+    key: String,
+    next_key: Option<String>,
+    // Mapを出力し始めたときの `entries` の長さ
+    start: usize,
+    // std::time::Duration の可能性がある `Duration` という名前の構造体
+    duration: bool,
+}
+
+impl MapSerializer<'_> {
+    fn field<T: Serialize + ?Sized>(&mut self, segment: &str, value: &T) -> Result<()> {
+        check_segment(&self.key, segment)?;
+        let key = join_key(&self.key, segment);
+        value.serialize(ValueSerializer { entries: self.entries, key })
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.duration {
+            // std::time::Duration は `secs` と `nanos` のフィールドとして渡されるため、`1500ms` のような時間の形式にまとめる
+            let fields: Vec<(String, String)> = self.entries.split_off(self.start);
+            let field = |name: &str| fields.iter().find(|(key, _)| key.rsplit('.').next() == Some(name)).and_then(|(_, value)| value.parse::<u64>().ok());
+            if let (2, Some(secs), Some(nanos)) = (fields.len(), field("secs"), field("nanos").and_then(|nanos| u32::try_from(nanos).ok())) {
+                self.entries.push((self.key, format_duration(Duration::new(secs, nanos))));
+                return Ok(());
+            }
+            self.entries.extend(fields);
+        }
+        check_written(self.entries, self.start, &self.key)
+    }
+}
+
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.next_key = Some(key.serialize(KeySerializer { parent: &self.key })?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let segment = self.next_key.take().expect("serialize_key の後に呼び出す");
+        self.field(&segment, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<()> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer<'_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, name: &'static str, value: &T) -> Result<()> {
+        self.field(name, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+// Mapのキーは文字列・数値・文字・unit variantのみ受け付ける
+struct KeySerializer<'a> {
+    parent: &'a str,
+}
+
+impl KeySerializer<'_> {
+    fn unsupported(&self) -> Error {
+        serialize_error(self.parent, "Mapのキーには文字列または数値を使用してください")
+    }
+}
+
+impl ser::Serializer for KeySerializer<'_> {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_none(self) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<String> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(self.unsupported())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(self.unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::de::from_str;
+    use crate::parser::{parse_config_str, ParseOptions};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Level {
+        Debug,
+        Info,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Log {
+        file: String,
+        level: Level,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        endpoint: String,
+        debug: bool,
+        port: u16,
+        ratio: f64,
+        offset: i64,
+        timeout: Duration,
+        retry: Duration,
+        servers: Vec<String>,
+        log: Log,
+        labels: BTreeMap<String, String>,
+        proxy: Option<String>,
+    }
+
+    #[test]
+    fn to_string_round_trips_through_from_str() {
+        let settings = Settings {
+            endpoint: "https://api.example.com/v1".to_string(),
+            debug: true,
+            port: 8080,
+            ratio: 0.75,
+            offset: i64::MIN,
+            timeout: Duration::from_millis(1500),
+            retry: Duration::new(3, 7),
+            servers: vec!["a.example.com".to_string(), "b.example.com".to_string()],
+            log: Log { file: "/var/log/app.log".to_string(), level: Level::Info },
+            labels: BTreeMap::from([("env".to_string(), "prod".to_string()), ("zone".to_string(), "0755".to_string())]),
+            proxy: None,
+        };
+        let text = to_string(&settings).unwrap();
+
+        assert!(text.contains("timeout = 1500ms\n"), "{}", text);
+        assert_eq!(from_str::<Settings>(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn values_that_cannot_round_trip_are_errors() {
+        #[derive(Serialize)]
+        struct Flags {
+            name: String,
+        }
+        #[derive(Serialize)]
+        struct Items {
+            items: Vec<u8>,
+        }
+
+        let error = |result: Result<String>| match result {
+            Err(Error::Serialize { key, .. }) => key,
+            other => panic!("Serialize を期待しましたが {:?} でした", other),
+        };
+        assert_eq!(error(to_string(&Flags { name: "True".to_string() })), "name");
+        assert_eq!(error(to_string(&Flags { name: " padded".to_string() })), "name");
+        assert_eq!(error(to_string(&Flags { name: "a\nb".to_string() })), "name");
+        assert_eq!(error(to_string(&Items { items: Vec::new() })), "items");
+    }
+
+    #[test]
+    fn parsed_values_round_trip() {
+        let source = "endpoint = https://api.example.com\ndebug = true\nservers.0 = a\nservers.1 = b\n\
+                      port = 0x1F\nratio = 1.0\ntimeout = 1500ms\nbuffer = 64KiB\nlog.file = /var/log/app.log\n";
+        for detect_types in [false, true] {
+            let options = ParseOptions { detect_types, ..ParseOptions::default() };
+            let parsed = parse_config_str(source, Path::new("test.conf"), &options);
+            let text = to_string(&parsed.values).unwrap();
+            let reparsed = parse_config_str(&text, Path::new("test.conf"), &options);
+
+            assert!(reparsed.diagnostics.is_empty(), "{}", text);
+            assert_eq!(reparsed.values, parsed.values, "{}", text);
+        }
+    }
+
+    #[test]
+    fn config_values_use_the_config_syntax() {
+        let config = BTreeMap::from([
+            ("timeout", ConfigValue::Duration(Duration::from_millis(1500))),
+            ("buffer", ConfigValue::Bytes(65536)),
+            ("ratio", ConfigValue::Float(1.0)),
+            ("debug", ConfigValue::Bool(false)),
+            ("ports", ConfigValue::List(vec![ConfigValue::Int(80), ConfigValue::Int(443)])),
+        ]);
+
+        assert_eq!(to_string(&config).unwrap(), "buffer = 65536B\ndebug = false\nports.0 = 80\nports.1 = 443\nratio = 1.0\ntimeout = 1500ms\n");
+    }
+
+    #[test]
+    fn index_keyed_maps_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Maps {
+            by_number: BTreeMap<u32, String>,
+            by_name: BTreeMap<String, String>,
+        }
+
+        let maps = Maps {
+            by_number: BTreeMap::from([(0, "a".to_string()), (1, "b".to_string())]),
+            by_name: BTreeMap::from([("0".to_string(), "x".to_string())]),
+        };
+        let text = to_string(&maps).unwrap();
+
+        assert_eq!(text, "by_number.0 = a\nby_number.1 = b\nby_name.0 = x\n");
+        assert_eq!(from_str::<Maps>(&text).unwrap(), maps);
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+
+/// 設定ファイルから読み込んだ値。ドット区切りのキーはネストした `Map` として保持する。
+///
+/// `Int` 以降の値は型の推定を有効にした場合か、スキーマがその型を要求した場合にのみ使われる。
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Map(HashMap<String, ConfigValue>),
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Duration(Duration),
+    /// バイト数
+    Bytes(u64),
+    /// `servers.0`, `servers.1` のような連番のキー、またはスキーマに従って分割した値
+    List(Vec<ConfigValue>),
+}
+
+lazy_static! {
+    static ref INT_REGEX: Regex = Regex::new(r"^[-+]?(0[xX][0-9a-fA-F]+|0[oO]?[0-7]+|[0-9]+)$").unwrap();
+    static ref FLOAT_REGEX: Regex = Regex::new(r"^[-+]?([0-9]+\.[0-9]*|\.[0-9]+|[0-9]+)([eE][-+]?[0-9]+)?$").unwrap();
+    static ref DURATION_REGEX: Regex = Regex::new(r"^([0-9]+(\.[0-9]+)?(ns|us|µs|ms|s|sec|min|m|h|d|w)\s*)+$").unwrap();
+    static ref DURATION_PART_REGEX: Regex = Regex::new(r"([0-9]+(?:\.[0-9]+)?)(ns|us|µs|ms|sec|s|min|m|h|d|w)").unwrap();
+    static ref SIZE_REGEX: Regex = Regex::new(r"^([0-9]+(?:\.[0-9]+)?)\s*([kKmMgGtTpP]i?[bB]?|[bB])$").unwrap();
+}
+
+/// 値の文字列を解釈する。`true`/`false` は常に真偽値とし、
+/// `detect_types` が有効な場合は数値・時間・バイト数も推定する。
+///
+/// 推定では、`08` のような8進数として不正な値や `i64` に収まらない値など、整数の形式で整数として解釈できない値と、
+/// `64m` のように時間 (分) とバイト数のどちらとも解釈できる値は文字列のままにする。
+pub(crate) fn parse_scalar(raw: &str, detect_types: bool) -> ConfigValue {
+    if raw.eq_ignore_ascii_case("true") || raw.eq_ignore_ascii_case("false") {
+        return ConfigValue::Bool(raw.eq_ignore_ascii_case("true"));
+    }
+    if detect_types {
+        if INT_REGEX.is_match(raw) {
+            return parse_int(raw).map(ConfigValue::Int).unwrap_or_else(|| ConfigValue::String(raw.to_string()));
+        }
+        if let Some(f) = parse_float(raw) {
+            return ConfigValue::Float(f);
+        }
+        match (parse_duration(raw), parse_size(raw)) {
+            (Some(d), None) => return ConfigValue::Duration(d),
+            (None, Some(b)) => return ConfigValue::Bytes(b),
+            _ => (),
+        }
+    }
+    ConfigValue::String(raw.to_string())
+}
+
+/// 10進数、`0x` 付きの16進数、`0o` または `0` 始まりの8進数を解釈する。
+pub(crate) fn parse_int(raw: &str) -> Option<i64> {
+    parse_wide_int(raw)?.try_into().ok()
+}
+
+/// [`parse_int`] と同じ形式で、`i64` に収まらない整数も解釈する。
+pub(crate) fn parse_wide_int(raw: &str) -> Option<i128> {
+    if !INT_REGEX.is_match(raw) {
+        return None;
+    }
+    let (negative, digits) = match raw.as_bytes()[0] {
+        b'-' => (true, &raw[1..]),
+        b'+' => (false, &raw[1..]),
+        _ => (false, raw),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16)
+    } else if let Some(oct) = digits.strip_prefix("0o").or_else(|| digits.strip_prefix("0O")) {
+        i128::from_str_radix(oct, 8)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    }
+    .ok()?;
+    Some(if negative { -magnitude } else { magnitude })
+}
+
+pub(crate) fn parse_float(raw: &str) -> Option<f64> {
+    FLOAT_REGEX.is_match(raw).then(|| raw.parse().ok()).flatten()
+}
+
+/// `500ms`, `2h`, `1h30m` のような時間を解釈する。整数の値はナノ秒単位で誤差なく解釈する。
+pub(crate) fn parse_duration(raw: &str) -> Option<Duration> {
+    if !DURATION_REGEX.is_match(raw) {
+        return None;
+    }
+    let mut nanos: u128 = 0;
+    for captures in DURATION_PART_REGEX.captures_iter(raw) {
+        let unit: u128 = match &captures[2] {
+            "ns" => 1,
+            "us" | "µs" => 1_000,
+            "ms" => 1_000_000,
+            "s" | "sec" => 1_000_000_000,
+            "m" | "min" => 60_000_000_000,
+            "h" => 3_600_000_000_000,
+            "d" => 86_400_000_000_000,
+            _ => 604_800_000_000_000,
+        };
+        nanos += match captures[1].parse::<u128>() {
+            Ok(amount) => amount.checked_mul(unit)?,
+            Err(_) => (captures[1].parse::<f64>().ok()? * unit as f64).round() as u128,
+        };
+    }
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// 時間を [`parse_duration`] で同じ値として読み戻せる文字列にする。端数のない最大の単位を使う。
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+    let (amount, unit) = [(1_000_000_000, "s"), (1_000_000, "ms"), (1_000, "us")]
+        .into_iter()
+        .find(|(unit_nanos, _)| nanos.is_multiple_of(*unit_nanos))
+        .map_or((nanos, "ns"), |(unit_nanos, unit)| (nanos / unit_nanos, unit));
+    format!("{}{}", amount, unit)
+}
+
+/// `64K`, `1GiB`, `10MB` のようなバイト数を解釈する。
+/// 単位の `K` と `KiB` は1024倍、`KB` は1000倍として扱う。
+pub(crate) fn parse_size(raw: &str) -> Option<u64> {
+    let captures = SIZE_REGEX.captures(raw)?;
+    let amount: f64 = captures[1].parse().ok()?;
+    let unit = &captures[2];
+    let exponent = match unit.to_ascii_lowercase().chars().next()? {
+        'b' => 0,
+        'k' => 1,
+        'm' => 2,
+        'g' => 3,
+        't' => 4,
+        _ => 5,
+    };
+    let decimal = unit.len() == 2 && unit.ends_with(['b', 'B']);
+    let base: f64 = if decimal { 1000.0 } else { 1024.0 };
+    let bytes = amount * base.powi(exponent);
+    (bytes <= u64::MAX as f64).then_some(bytes as u64)
+}
+
+impl ConfigValue {
+    /// スキーマで使用する型名 (`string`, `bool`, `map`, `int` など) を返す。
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ConfigValue::String(_) => "string",
+            ConfigValue::Map(_) => "map",
+            ConfigValue::Bool(_) => "bool",
+            ConfigValue::Int(_) => "int",
+            ConfigValue::Float(_) => "float",
+            ConfigValue::Duration(_) => "duration",
+            ConfigValue::Bytes(_) => "size",
+            ConfigValue::List(_) => "list",
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&HashMap<String, ConfigValue>> {
+        if let ConfigValue::Map(m) = self {
+            Some(m)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_map_mut(&mut self) -> Option<&mut HashMap<String, ConfigValue>> {
+        if let ConfigValue::Map(m) = self {
+            Some(m)
+        } else {
+            None
+        }
+    }
+
+    /// `Map` の場合はキーで、`List` の場合は添字で子の値を返す。
+    pub fn get(&self, segment: &str) -> Option<&ConfigValue> {
+        match self {
+            ConfigValue::Map(m) => m.get(segment),
+            ConfigValue::List(items) => items.get(parse_index(segment)?),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, segment: &str) -> Option<&mut ConfigValue> {
+        match self {
+            ConfigValue::Map(m) => m.get_mut(segment),
+            ConfigValue::List(items) => items.get_mut(parse_index(segment)?),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_section(&self) -> bool {
+        matches!(self, ConfigValue::Map(_) | ConfigValue::List(_))
+    }
+
+    // キーを追加・削除できるように、`List` を添字をキーとする `Map` に戻す
+    fn as_section_mut(&mut self) -> Option<&mut HashMap<String, ConfigValue>> {
+        if let ConfigValue::List(items) = self {
+            let map = std::mem::take(items).into_iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect();
+            *self = ConfigValue::Map(map);
+        }
+        self.as_map_mut()
+    }
+}
+
+// 先頭に余分な 0 が付いていない10進数のみを添字とみなす
+fn parse_index(segment: &str) -> Option<usize> {
+    let index: usize = segment.parse().ok()?;
+    (index.to_string() == segment).then_some(index)
+}
+
+/// キーが `0` から連続した添字だけの `Map` を `List` に変換する。
+pub(crate) fn assemble_lists(config: &mut HashMap<String, ConfigValue>) {
+    for value in config.values_mut() {
+        assemble_value(value);
+    }
+}
+
+fn assemble_value(value: &mut ConfigValue) {
+    match value {
+        ConfigValue::Map(m) => {
+            assemble_lists(m);
+            let is_list = !m.is_empty() && m.keys().all(|k| parse_index(k).is_some_and(|i| i < m.len()));
+            if is_list {
+                let mut entries: Vec<(usize, ConfigValue)> =
+                    std::mem::take(m).into_iter().map(|(k, v)| (parse_index(&k).unwrap(), v)).collect();
+                entries.sort_by_key(|(i, _)| *i);
+                *value = ConfigValue::List(entries.into_iter().map(|(_, v)| v).collect());
+            }
+        }
+        ConfigValue::List(items) => items.iter_mut().for_each(assemble_value),
+        _ => (),
+    }
+}
+
+// 途中のセクションがスカラー値だった場合や、セクションをスカラー値で上書きしようとした場合は
+// 衝突したキーパスを返す
+pub(crate) fn insert_config_value(config: &mut HashMap<String, ConfigValue>, key: &str, value: ConfigValue) -> Result<(), String> {
+    let keys: Vec<&str> = key.split('.').collect();
+    let mut map = config;
+
+    for (i, sub_key) in keys[..keys.len() - 1].iter().enumerate() {
+        map = map.entry(sub_key.to_string())
+            .or_insert_with(|| ConfigValue::Map(HashMap::new()))
+            .as_section_mut()
+            .ok_or_else(|| keys[..=i].join("."))?;
+    }
+
+    let leaf = keys.last().unwrap();
+    if map.get(*leaf).is_some_and(ConfigValue::is_section) && !value.is_section() {
+        return Err(key.to_string());
+    }
+    map.insert(leaf.to_string(), value);
+    Ok(())
+}
+
+pub(crate) fn remove_config_value(config: &mut HashMap<String, ConfigValue>, key: &str) -> Option<ConfigValue> {
+    let keys: Vec<&str> = key.split('.').collect();
+    let mut map = config;
+
+    for sub_key in &keys[..keys.len() - 1] {
+        map = map.get_mut(*sub_key)?.as_section_mut()?;
+    }
+
+    map.remove(*keys.last().unwrap())
+}
+
+/// [`lookup_path`] でキーを解決できなかった理由。値は解決に失敗した位置までのキーパス。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupError {
+    /// 途中のセクションが存在しない
+    MissingSection(String),
+    /// 途中のセクションがスカラー値になっている
+    NotAMap(String),
+    /// 最後のキーが存在しない
+    MissingLeaf(String),
+}
+
+pub(crate) fn lookup_path_mut<'a>(config: &'a mut HashMap<String, ConfigValue>, key: &str) -> Option<&'a mut ConfigValue> {
+    let mut keys = key.split('.');
+    let mut value = config.get_mut(keys.next()?)?;
+
+    for sub_key in keys {
+        value = value.get_mut(sub_key)?;
+    }
+
+    Some(value)
+}
+
+/// ドット区切りのキー (`log.file`, `servers.0` など) をネストした `Map` と `List` を辿って解決する。
+pub fn lookup_path<'a>(config: &'a HashMap<String, ConfigValue>, key: &str) -> Result<&'a ConfigValue, LookupError> {
+    let keys: Vec<&str> = key.split('.').collect();
+    let mut value = config.get(keys[0]);
+
+    for (i, sub_key) in keys.iter().enumerate().skip(1) {
+        let path = keys[..i].join(".");
+        value = match value {
+            Some(section) if section.is_section() => section.get(sub_key),
+            Some(_) => return Err(LookupError::NotAMap(path)),
+            None => return Err(LookupError::MissingSection(path)),
+        };
+    }
+
+    value.ok_or_else(|| LookupError::MissingLeaf(key.to_string()))
+}
+
+/// 設定をJSONオブジェクトに変換する。数値とバイト数はJSONの数値に、時間は秒数に、`List` は配列にする。
+pub fn format_as_json(config: &HashMap<String, ConfigValue>) -> serde_json::Value {
+    let mut json_obj = serde_json::Map::new();
+    for (key, value) in config {
+        json_obj.insert(key.clone(), value_as_json(value));
+    }
+    serde_json::Value::Object(json_obj)
+}
+
+pub(crate) fn value_as_json(value: &ConfigValue) -> serde_json::Value {
+    match value {
+        ConfigValue::String(s) => json!(s),
+        ConfigValue::Map(m) => format_as_json(m),
+        ConfigValue::Bool(b) => json!(b),
+        ConfigValue::Int(n) => json!(n),
+        ConfigValue::Float(f) => json!(f),
+        ConfigValue::Duration(d) if d.subsec_nanos() == 0 => json!(d.as_secs()),
+        ConfigValue::Duration(d) => json!(d.as_secs_f64()),
+        ConfigValue::Bytes(b) => json!(b),
+        ConfigValue::List(items) => serde_json::Value::Array(items.iter().map(value_as_json).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> ConfigValue {
+        ConfigValue::String(s.to_string())
+    }
+
+    #[test]
+    fn insert_config_value_creates_nested_sections() {
+        let mut config = HashMap::new();
+        insert_config_value(&mut config, "log.file", string("a.log")).unwrap();
+        insert_config_value(&mut config, "log.level", string("info")).unwrap();
+
+        assert_eq!(lookup_path(&config, "log.file"), Ok(&string("a.log")));
+        assert_eq!(lookup_path(&config, "log.level"), Ok(&string("info")));
+    }
+
+    #[test]
+    fn insert_config_value_reports_conflicting_path() {
+        let mut config = HashMap::new();
+        insert_config_value(&mut config, "log", string("on")).unwrap();
+        assert_eq!(insert_config_value(&mut config, "log.file.name", string("x")), Err("log".to_string()));
+
+        let mut config = HashMap::new();
+        insert_config_value(&mut config, "log.file", string("x")).unwrap();
+        assert_eq!(insert_config_value(&mut config, "log", string("on")), Err("log".to_string()));
+        assert_eq!(lookup_path(&config, "log.file"), Ok(&string("x")));
+    }
+
+    #[test]
+    fn assemble_lists_converts_contiguous_indices() {
+        let mut config = HashMap::new();
+        insert_config_value(&mut config, "servers.1.host", string("b")).unwrap();
+        insert_config_value(&mut config, "servers.0.host", string("a")).unwrap();
+        insert_config_value(&mut config, "ports.0", string("80")).unwrap();
+        assemble_lists(&mut config);
+
+        let host = |name: &str| ConfigValue::Map(HashMap::from([("host".to_string(), string(name))]));
+        assert_eq!(config["servers"], ConfigValue::List(vec![host("a"), host("b")]));
+        assert_eq!(config["ports"], ConfigValue::List(vec![string("80")]));
+    }
+
+    #[test]
+    fn assemble_lists_keeps_maps_without_contiguous_indices() {
+        let mut config = HashMap::new();
+        insert_config_value(&mut config, "gap.0", string("a")).unwrap();
+        insert_config_value(&mut config, "gap.2", string("c")).unwrap();
+        insert_config_value(&mut config, "padded.00", string("a")).unwrap();
+        assemble_lists(&mut config);
+
+        assert!(matches!(config["gap"], ConfigValue::Map(_)));
+        assert!(matches!(config["padded"], ConfigValue::Map(_)));
+    }
+
+    #[test]
+    fn parse_scalar_detects_integers_with_one_grammar() {
+        assert_eq!(parse_scalar("0x1F", true), ConfigValue::Int(31));
+        assert_eq!(parse_scalar("0755", true), ConfigValue::Int(0o755));
+        assert_eq!(parse_scalar("-9223372036854775808", true), ConfigValue::Int(i64::MIN));
+        // 8進数として不正な値や範囲外の値は浮動小数点数にせず文字列のままにする
+        assert_eq!(parse_scalar("08", true), string("08"));
+        assert_eq!(parse_scalar("9223372036854775808", true), string("9223372036854775808"));
+        assert_eq!(parse_scalar("1.5", true), ConfigValue::Float(1.5));
+        assert_eq!(parse_scalar("10", false), string("10"));
+    }
+
+    #[test]
+    fn parse_scalar_keeps_ambiguous_units_as_strings() {
+        assert_eq!(parse_scalar("64m", true), string("64m"));
+        assert_eq!(parse_scalar("64min", true), ConfigValue::Duration(Duration::from_secs(64 * 60)));
+        assert_eq!(parse_scalar("64M", true), ConfigValue::Bytes(64 * 1024 * 1024));
+    }
+
+    #[test]
+    fn format_duration_uses_largest_exact_unit() {
+        assert_eq!(format_duration(Duration::from_secs(30)), "30s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(format_duration(Duration::new(3, 7)), "3000000007ns");
+        assert_eq!(parse_duration(&format_duration(Duration::new(3, 7))), Some(Duration::new(3, 7)));
+    }
+}